@@ -2,9 +2,20 @@ mod backend;
 mod env;
 mod frontend;
 
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::{message::Message, Args};
+use crate::{
+    db,
+    export::{self, ExportFormat},
+    import::{self, Names},
+    message::{ConversationSummary, Message, SearchHit},
+    sampling::SamplingParameters,
+    Args,
+};
+use anyhow::Context;
 use backend::BackendState;
 use env::Env;
 use frontend::FrontendState;
@@ -21,7 +32,8 @@ impl App {
         let (f_tx, f_rx) = unbounded_channel::<Event>();
         let (b_tx, b_rx) = unbounded_channel::<Event>();
 
-        let mut frontend = FrontendState::new(f_rx, b_tx, app_tx.clone(), env.clone()).await?;
+        let mut frontend =
+            FrontendState::new(f_rx, b_tx, app_tx.clone(), env.clone(), &args).await?;
         let mut backend = BackendState::new(b_rx, f_tx, app_tx, env.clone(), &args).await?;
 
         trace!("frontend and backend state has been initialized, starting main loop");
@@ -67,6 +79,117 @@ impl App {
 
         Ok(())
     }
+
+    /// Print the messages matching `query`, best matches first.
+    pub fn search(args: &Args, query: &str, limit: usize) -> Result<(), anyhow::Error> {
+        let env = Env::new(args)?;
+        let conn = db::open_database(env.database_file_path())?;
+
+        let hits = db::search_messages(&conn, query, limit)?;
+        if hits.is_empty() {
+            println!("No messages matched \"{query}\".");
+            return Ok(());
+        }
+
+        for hit in hits {
+            println!(
+                "conversation {} · {} · {}",
+                hit.conversation_id,
+                hit.timestamp.format("%Y-%m-%d %H:%M"),
+                hit.sender
+            );
+            println!("    {}\n", hit.plain_snippet());
+        }
+        println!("Run with --view <conversation> to read a whole conversation.");
+
+        Ok(())
+    }
+
+    /// Export one saved conversation, or every one of them when `conversation_id` is `None`.
+    pub fn export(
+        args: &Args,
+        conversation_id: Option<i64>,
+        format: ExportFormat,
+        output: Option<&Path>,
+    ) -> Result<(), anyhow::Error> {
+        let env = Env::new(args)?;
+        let conn = db::open_database(env.database_file_path())?;
+
+        let Some(id) = conversation_id else {
+            let dir = output.unwrap_or(Path::new("."));
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+
+            let conversations = db::list_conversations(&conn)?;
+            for conversation in &conversations {
+                let path = dir.join(export::file_name(conversation.id, format));
+                let exported = export::export_conversation(&conn, conversation.id, format)?;
+                std::fs::write(&path, exported)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+            }
+            println!(
+                "Exported {} conversations to {}",
+                conversations.len(),
+                dir.display()
+            );
+
+            return Ok(());
+        };
+
+        let exported = export::export_conversation(&conn, id, format)?;
+        match output {
+            Some(path) => {
+                std::fs::write(path, exported)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+                println!("Exported conversation {id} to {}", path.display());
+            }
+            None => print!("{exported}"),
+        }
+
+        Ok(())
+    }
+
+    /// Save the conversations in each of `files`, skipping ones that were imported before.
+    pub fn import(args: &Args, files: &[PathBuf]) -> Result<(), anyhow::Error> {
+        let env = Env::new(args)?;
+        let mut conn = db::open_database(env.database_file_path())?;
+        let names = Names {
+            user: env.your_name(),
+            bot: env.their_name(),
+        };
+
+        for path in files {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let conversations = import::parse(&text, &names)
+                .with_context(|| format!("failed to import {}", path.display()))?;
+
+            let mut imported = 0;
+            for conversation in &conversations {
+                let prompt = conversation
+                    .prompt
+                    .as_deref()
+                    .unwrap_or(env.starting_prompt());
+                if db::insert_imported_conversation(
+                    &mut conn,
+                    &conversation.external_id,
+                    prompt,
+                    &conversation.messages,
+                )?
+                .is_some()
+                {
+                    imported += 1;
+                }
+            }
+            println!(
+                "{}: imported {imported}, skipped {} that were already imported",
+                path.display(),
+                conversations.len() - imported
+            );
+        }
+
+        Ok(())
+    }
 }
 
 type EventRx = UnboundedReceiver<Event>;
@@ -76,6 +199,53 @@ enum Event {
     /// Any handler receiving this event should put its affairs in order.
     Quit,
     UserMessage(String),
+    /// Send a new version of the user's message with the given ID, starting a new branch of the
+    /// conversation from there.
+    EditMessage(u64, String),
+    /// Switch to the branch through the sibling before or after the message with the given ID.
+    PreviousBranch(u64),
+    NextBranch(u64),
     ConversationUpdated(Vec<Message>),
+    /// The next few tokens of the bot's response, sent while the response is being streamed.
+    BotResponseDelta(String),
+    /// The bot's response is being asked for again, so anything streamed of it so far is wrong.
+    BotResponseRestarted,
+    /// The bot couldn't respond. Contains a description of what went wrong.
+    BotResponseFailed(String),
+    /// Ask the bot to try responding to the user's last message again, or to try regenerating its
+    /// last response again if that's what failed.
+    RetryBotResponse,
+    /// Ask the bot for another response in place of its last one. Every response is kept as an
+    /// alternate.
+    RegenerateBotResponse,
+    /// Ask the bot to finish its last response, which ran into the token limit. The rest is added
+    /// to the same message.
+    ContinueBotResponse,
+    /// Switch the bot's last response to the alternate before or after it.
+    PreviousAlternate,
+    NextAlternate,
+    /// Use these sampling parameters for the bot's responses from now on.
+    ChangeSampling(SamplingParameters),
+    /// Stop waiting for the bot's response and throw it away.
+    CancelBotResponse,
+    /// The bot's response was cancelled. Contains the user's message that went unanswered, which
+    /// is removed from the conversation.
+    BotResponseCancelled(Option<String>),
     StatusUpdated(String),
+    /// Ask for a summary of every saved conversation.
+    ListConversations,
+    ConversationsListed(Vec<ConversationSummary>),
+    /// Ask for a saved conversation's messages, to look at without resuming it. With a message ID,
+    /// the branch that message is on is shown instead of the newest one.
+    ViewConversation(i64, Option<u64>),
+    ConversationViewed(i64, Vec<Message>),
+    /// Switch to a saved conversation and pick it up where it left off.
+    ResumeConversation(i64),
+    /// Search every saved conversation for messages containing all of the given words.
+    Search(String),
+    SearchResults(Vec<SearchHit>),
+    /// Export a saved conversation as Markdown, or the current conversation if there's no ID.
+    ExportConversation(Option<i64>),
+    /// Contains where the conversation was exported to, or why it couldn't be.
+    ConversationExported(String),
 }
@@ -1,45 +1,109 @@
-mod db;
-
 use super::env::Env;
 use super::{Event, EventRx, EventTx};
-use crate::message::Message;
-use crate::openai_api::fetch_response_to_prompt;
-use crate::Args;
-use db::{
-    begin_new_conversation, commit_conversation_to_database,
-    load_previous_conversation_from_database, save_database_to_file,
+use crate::db::{
+    delete_conversation, delete_message, get_messages_by_conversation_id, get_newest_branch,
+    insert_conversation, insert_message, list_conversations, load_previous_conversation,
+    open_database, save_alternates, save_continuation, search_messages,
+};
+use crate::export::{self, ExportFormat};
+use crate::message::{branch, Message};
+use crate::openai_api::{ApiConfig, OpenAiProvider, RetryPolicy};
+use crate::provider::{
+    EchoProvider, Provider, ProviderKind, ResponseProgress, ResponseRequest, ScriptedProvider,
 };
+use crate::sampling::SamplingParameters;
+use crate::tokenizer::Tokenizer;
+use crate::Args;
+use anyhow::Context;
 use rusqlite::Connection;
 use std::mem;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::mpsc;
-use tracing::{debug, instrument, trace};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, instrument, trace};
+
+// Nobody's going to read through more search results than this
+const SEARCH_RESULT_LIMIT: usize = 50;
 
 // The Unpin in this feels wrong but I'm not sure
 
 enum Inner {
     // The app is currently waiting for a response from OpenAI
     BotsTurn,
+    // The bot gets another go at its last response, which is kept as an alternate
+    BotsTurnToRegenerate,
+    // The bot picks up where its last response was cut off
+    BotsTurnToContinue,
     LoadingBotResponse {
         start_time: Instant,
-        rx: mpsc::Receiver<Message>,
+        pending: PendingResponse,
     },
     TakingAWhileToLoadBotResponse {
         start_time: Instant,
-        rx: mpsc::Receiver<Message>,
+        pending: PendingResponse,
     },
     UsersTurn,
+    // A saved conversation is only being looked at, so the bot never gets a turn
+    ReadOnly,
+}
+
+// The receiving ends of the channels used by the task fetching the bot's response.
+struct PendingResponse {
+    // The task fetching the response, kept so that the request can be cancelled
+    handle: JoinHandle<()>,
+    rx: mpsc::Receiver<Result<Message, anyhow::Error>>,
+    progress_rx: mpsc::UnboundedReceiver<ResponseProgress>,
+    // Set once the first bit of a streamed response arrives
+    is_streaming: bool,
+    // The attempt being made and the total allowed, once the first attempt has failed
+    retrying: Option<(u32, u32)>,
+    kind: ResponseKind,
+}
+
+// What's being asked of the bot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseKind {
+    New,
+    // Another take on the bot's last message
+    Regeneration,
+    // The rest of the bot's last message, which was cut off
+    Continuation,
+}
+
+impl PendingResponse {
+    // A status describing the response's progress, if there's anything more to say than "waiting"
+    fn status(&self, their_name: &str) -> Option<String> {
+        if let Some((attempt, max_attempts)) = self.retrying {
+            Some(format!("retrying ({attempt}/{max_attempts})…"))
+        } else if self.is_streaming {
+            Some(format!("{their_name} is typing..."))
+        } else {
+            None
+        }
+    }
 }
 
 pub struct BackendState {
     _app_tx: EventTx,
     conn: Connection,
     conversation: Vec<Message>,
+    // Set once the conversation has been saved to the database, which happens with its first message
+    conversation_id: Option<i64>,
     frontend_tx: EventTx,
     inner: Inner,
+    // Not needed when only viewing a conversation
+    provider: Option<Box<dyn Provider>>,
     rx: EventRx,
     env: Arc<Env>,
+    // Starts out as what's in the env, and can be changed in the settings
+    sampling: SamplingParameters,
+    // What the bot was asked for when it last failed to respond, so that it can be asked again
+    failed: Option<ResponseKind>,
+    // The ID of the message the user just sent, until the bot answers it. Cancelling the bot's
+    // response takes it back. Messages from earlier turns are left alone.
+    unanswered_message: Option<u64>,
+    tokenizer: Tokenizer,
 }
 
 impl BackendState {
@@ -50,12 +114,43 @@ impl BackendState {
         env: Arc<Env>,
         args: &Args,
     ) -> Result<Self, anyhow::Error> {
-        let (conn, previous_conversation) = if args.resume() {
-            load_previous_conversation_from_database(env.database_file_path())?
+        let conn = open_database(env.database_file_path())?;
+        let tokenizer = Tokenizer::for_model(env.openai_model_name())?;
+
+        if let Some(id) = args.view() {
+            let messages = get_newest_branch(&conn, id)?;
+            frontend_tx
+                .send(Event::ConversationViewed(id, messages))
+                .map_err(|e| anyhow::anyhow!("Failed to send conversation to frontend: {e}"))?;
+
+            return Ok(Self {
+                _app_tx: app_tx,
+                conn,
+                conversation: Vec::new(),
+                conversation_id: None,
+                frontend_tx,
+                inner: Inner::ReadOnly,
+                provider: None,
+                rx,
+                sampling: env.sampling().clone(),
+                failed: None,
+                unanswered_message: None,
+                env,
+                tokenizer,
+            });
+        }
+
+        let (conversation_id, previous_conversation) = if args.resume() {
+            match load_previous_conversation(&conn)? {
+                Some((id, messages)) => (Some(id), messages),
+                None => (None, Vec::new()),
+            }
         } else {
-            begin_new_conversation(env.database_file_path())?
+            (None, Vec::new())
         };
 
+        let provider = new_provider(&env)?;
+
         let is_users_turn = previous_conversation.is_empty()
             || previous_conversation.last().unwrap().sender == env.their_name();
 
@@ -79,10 +174,16 @@ impl BackendState {
             _app_tx: app_tx,
             conn,
             conversation: previous_conversation,
+            conversation_id,
             frontend_tx,
             inner,
+            provider: Some(provider),
             rx,
+            sampling: env.sampling().clone(),
+            failed: None,
+            unanswered_message: None,
             env,
+            tokenizer,
         })
     }
 
@@ -95,13 +196,27 @@ impl BackendState {
                     Event::Quit => {
                         // App will call the quit method. We can't call it because it consumes self.
                     }
+                    Event::UserMessage(_)
+                    | Event::EditMessage(..)
+                    | Event::ResumeConversation(_)
+                        if matches!(self.inner, Inner::ReadOnly) =>
+                    {
+                        debug!("ignoring attempt to chat while only viewing a conversation");
+                    }
                     Event::UserMessage(content) => {
-                        let message = Message {
+                        let message = self.save_message(Message {
                             sender: self.env.your_name().to_owned(),
                             content,
                             timestamp: chrono::Utc::now(),
-                            id: self.conversation.len() as u64,
-                        };
+                            // Saving the message gives it a real ID
+                            id: 0,
+                            usage: None,
+                            alternates: None,
+                            parent: None,
+                            siblings: None,
+                            sampling: None,
+                            truncated: false,
+                        })?;
                         trace!(
                             message.timestamp = message.timestamp.to_rfc2822().as_str(),
                             message.id = message.id,
@@ -109,6 +224,7 @@ impl BackendState {
                             "user sent message"
                         );
 
+                        self.unanswered_message = Some(message.id);
                         self.conversation.push(message);
                         // Immediately send the conversation to the frontend so that the user's
                         // message will be displayed immediately, instead of after the bot responds.
@@ -122,6 +238,87 @@ impl BackendState {
 
                         self.inner = Inner::BotsTurn;
                     }
+                    Event::EditMessage(id, content) => {
+                        self.edit_message(id, content)?;
+                    }
+                    Event::PreviousBranch(id) | Event::NextBranch(id) => {
+                        let forward = matches!(event, Event::NextBranch(_));
+                        self.switch_branch(id, forward)?;
+                    }
+                    Event::CancelBotResponse => {
+                        self.cancel_bot_response()?;
+                    }
+                    Event::RetryBotResponse => {
+                        if self.failed == Some(ResponseKind::Continuation) {
+                            self.continue_bot_response()?;
+                        } else if matches!(self.inner, Inner::UsersTurn) && self.is_waiting_on_bot()
+                        {
+                            debug!("retrying {}'s response", self.env.their_name());
+                            self.inner = Inner::BotsTurn;
+                        } else {
+                            // The bot already answered, so it was regenerating that failed
+                            self.regenerate_bot_response()?;
+                        }
+                    }
+                    Event::RegenerateBotResponse => {
+                        self.regenerate_bot_response()?;
+                    }
+                    Event::ContinueBotResponse => {
+                        self.continue_bot_response()?;
+                    }
+                    Event::PreviousAlternate | Event::NextAlternate => {
+                        let forward = matches!(event, Event::NextAlternate);
+                        self.cycle_alternates(forward)?;
+                    }
+                    Event::ChangeSampling(sampling) => {
+                        debug!(?sampling, "changing sampling parameters");
+                        self.sampling = sampling;
+                    }
+                    Event::ListConversations => {
+                        let conversations = list_conversations(&self.conn)?;
+                        self.frontend_tx
+                            .send(Event::ConversationsListed(conversations))
+                            .map_err(|e| {
+                                anyhow::anyhow!("failed to send conversations to frontend: {e}")
+                            })?;
+                    }
+                    Event::ViewConversation(id, message_id) => {
+                        let messages = get_messages_by_conversation_id(&self.conn, id)?;
+                        let messages = match message_id {
+                            Some(message_id) => branch::branch_through(&messages, message_id),
+                            None => branch::newest_branch(&messages),
+                        };
+                        self.frontend_tx
+                            .send(Event::ConversationViewed(id, messages))
+                            .map_err(|e| {
+                                anyhow::anyhow!("failed to send conversation to frontend: {e}")
+                            })?;
+                    }
+                    Event::ResumeConversation(id) => {
+                        self.resume_conversation(id)?;
+                    }
+                    Event::Search(query) => {
+                        let hits = search_messages(&self.conn, &query, SEARCH_RESULT_LIMIT)?;
+                        self.frontend_tx
+                            .send(Event::SearchResults(hits))
+                            .map_err(|e| {
+                                anyhow::anyhow!("failed to send search results to frontend: {e}")
+                            })?;
+                    }
+                    Event::ExportConversation(id) => {
+                        let notice = match id.or(self.conversation_id) {
+                            Some(id) => match self.export_conversation(id) {
+                                Ok(path) => format!("Exported the conversation to {path}"),
+                                Err(e) => format!("Couldn't export the conversation: {e:#}"),
+                            },
+                            None => "There's nothing to export yet.".to_owned(),
+                        };
+                        self.frontend_tx
+                            .send(Event::ConversationExported(notice))
+                            .map_err(|e| {
+                                anyhow::anyhow!("failed to send export notice to frontend: {e}")
+                            })?;
+                    }
                     _ => {}
                 },
                 Err(e) => match e {
@@ -137,167 +334,582 @@ impl BackendState {
 
         trace!("driving state machine...");
         match &mut self.inner {
-            Inner::BotsTurn => {
+            Inner::BotsTurn | Inner::BotsTurnToRegenerate | Inner::BotsTurnToContinue => {
                 trace!("handling bot's turn...");
-                let id = self.conversation.len() as u64;
-                let prompt = create_prompt_from_messages(
+                let Some(provider) = &self.provider else {
+                    anyhow::bail!("the bot can't respond while only viewing a conversation");
+                };
+                let kind = match self.inner {
+                    Inner::BotsTurnToRegenerate => ResponseKind::Regeneration,
+                    Inner::BotsTurnToContinue => ResponseKind::Continuation,
+                    _ => ResponseKind::New,
+                };
+                let conversation = conversation_so_far(&self.conversation, kind);
+                let id = conversation.len() as u64;
+                let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+                // Only as much of the conversation as fits in the model's context window is sent
+                let recent_messages = match self.env.prompt_context_length() {
+                    Some(limit) => &conversation[conversation.len().saturating_sub(limit)..],
+                    None => conversation,
+                };
+                // Several alternates come back all at once, so they can't be streamed
+                let n = if kind == ResponseKind::Regeneration {
+                    self.env.alternates()
+                } else {
+                    1
+                };
+                let history = self.tokenizer.fit_history(
                     self.env.starting_prompt(),
-                    &self.conversation,
-                    self.env.prompt_context_length(),
-                );
-                let req = fetch_response_to_prompt(
-                    id,
-                    prompt,
-                    self.env.their_name().to_owned(),
-                    self.env.openai_model_name().to_owned(),
+                    recent_messages,
                     self.env.token_limit(),
                 );
+                let req = provider.fetch_response(
+                    ResponseRequest {
+                        id,
+                        starting_prompt: self.env.starting_prompt().to_owned(),
+                        your_name: self.env.your_name().to_owned(),
+                        their_name: self.env.their_name().to_owned(),
+                        history: history.to_vec(),
+                        model: self.env.openai_model_name().to_owned(),
+                        max_tokens: self.env.token_limit(),
+                        stream: self.env.stream_responses() && n == 1,
+                        n,
+                        sampling: self.sampling.clone(),
+                        continuing: kind == ResponseKind::Continuation,
+                    },
+                    progress_tx,
+                );
                 let (tx, rx) = mpsc::channel(1);
 
-                tokio::spawn(async move {
-                    // TODO don't unwrap here
-                    let response = req.await.unwrap();
-                    tx.send(response).await.unwrap();
+                let sampling = self.sampling.clone();
+                let handle = tokio::spawn(async move {
+                    let response = req.await.map(|mut message| {
+                        message.record_sampling(&sampling);
+                        message
+                    });
+                    // The backend stops listening if the user quits before the response arrives
+                    let _ = tx.send(response).await;
                 });
 
                 self.inner = Inner::LoadingBotResponse {
                     start_time: Instant::now(),
-                    rx,
+                    pending: PendingResponse {
+                        handle,
+                        rx,
+                        progress_rx,
+                        is_streaming: false,
+                        retrying: None,
+                        kind,
+                    },
                 };
 
                 Ok(())
             }
-            Inner::LoadingBotResponse { start_time, rx } => {
+            Inner::LoadingBotResponse {
+                start_time,
+                pending,
+            } => {
                 trace!("loading bot response...");
-                if start_time.elapsed() > self.env.expected_response_time() {
+                forward_response_progress(pending, &self.frontend_tx)?;
+
+                if !pending.is_streaming && start_time.elapsed() > self.env.expected_response_time()
+                {
                     trace!(
                         "{} is taking longer than {:?} to respond",
                         self.env.their_name(),
                         self.env.expected_response_time()
                     );
-                    let start_time = *start_time;
-                    let rx = mem::replace(rx, mpsc::channel(1).1);
-                    self.inner = Inner::TakingAWhileToLoadBotResponse { start_time, rx };
+                    if let Inner::LoadingBotResponse {
+                        start_time,
+                        pending,
+                    } = mem::replace(&mut self.inner, Inner::BotsTurn)
+                    {
+                        self.inner = Inner::TakingAWhileToLoadBotResponse {
+                            start_time,
+                            pending,
+                        };
+                    }
 
                     return Ok(());
                 }
 
+                let status = pending
+                    .status(self.env.their_name())
+                    .unwrap_or_else(|| "Waiting for bot's response".to_owned());
                 self.frontend_tx
-                    .send(Event::StatusUpdated(
-                        "Waiting for bot's response".to_owned(),
-                    ))
+                    .send(Event::StatusUpdated(status))
                     .map_err(|e| {
                         anyhow::anyhow!("failed to notify frontend of status update: {}", e)
                     })?;
 
-                // TODO this code is copied in the below handler, how can this be avoided?
-                if let Some(message) = check_for_bot_response(self.env.their_name(), rx) {
-                    self.conversation.push(message);
-                    self.frontend_tx
-                        .send(Event::ConversationUpdated(self.conversation.clone()))
-                        .map_err(|e| {
-                            anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
-                        })?;
-                    self.frontend_tx
-                        .send(Event::StatusUpdated(format!(
-                            "Bot responded in {:?}",
-                            start_time.elapsed()
-                        )))
-                        .map_err(|e| {
-                            anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
-                        })?;
-                    self.inner = Inner::UsersTurn;
+                let (start_time, kind) = (*start_time, pending.kind);
+                if let Some(response) =
+                    check_for_bot_response(self.env.their_name(), &mut pending.rx)
+                {
+                    self.receive_bot_response(
+                        response,
+                        kind,
+                        format!("Bot responded in {:?}", start_time.elapsed()),
+                    )?;
                 }
 
                 Ok(())
             }
-            Inner::TakingAWhileToLoadBotResponse { start_time, rx } => {
+            Inner::TakingAWhileToLoadBotResponse {
+                start_time,
+                pending,
+            } => {
                 trace!("loading bot response (taking a while)...");
-                self.frontend_tx
-                    .send(Event::StatusUpdated(format!(
+                forward_response_progress(pending, &self.frontend_tx)?;
+                let status = pending.status(self.env.their_name()).unwrap_or_else(|| {
+                    format!(
                         "Waiting for bot's response, It's taking a while ({}s)",
                         start_time.elapsed().as_secs()
-                    )))
+                    )
+                });
+                self.frontend_tx
+                    .send(Event::StatusUpdated(status))
                     .map_err(|e| {
                         anyhow::anyhow!("failed to notify frontend of status update: {}", e)
                     })?;
 
-                if let Some(message) = check_for_bot_response(self.env.their_name(), rx) {
-                    debug!("received response from {}", self.env.their_name());
-                    self.conversation.push(message);
-                    self.frontend_tx
-                        .send(Event::ConversationUpdated(self.conversation.clone()))
-                        .map_err(|e| {
-                            anyhow::anyhow!(
-                                "failed to notify frontend of conversation update: {}",
-                                e
-                            )
-                        })?;
-                    self.frontend_tx
-                        .send(Event::StatusUpdated(format!(
-                            "Bot slowly responded in {:?}",
-                            start_time.elapsed()
-                        )))
-                        .map_err(|e| {
-                            anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
-                        })?;
-                    self.inner = Inner::UsersTurn;
+                let (start_time, kind) = (*start_time, pending.kind);
+                if let Some(response) =
+                    check_for_bot_response(self.env.their_name(), &mut pending.rx)
+                {
+                    self.receive_bot_response(
+                        response,
+                        kind,
+                        format!("Bot slowly responded in {:?}", start_time.elapsed()),
+                    )?;
                 }
 
                 Ok(())
             }
-            Inner::UsersTurn => {
+            Inner::UsersTurn | Inner::ReadOnly => {
                 // The backend has nothing to do but wait for a response from the user
                 Ok(())
             }
         }
     }
 
-    pub async fn quit(self) -> Result<(), anyhow::Error> {
-        let mut conn = self.conn;
-        commit_conversation_to_database(
-            &mut conn,
-            self.env.starting_prompt(),
-            &self.conversation,
-        )?;
-        save_database_to_file(&conn, self.env.database_file_path())?;
+    /// Stop waiting on the bot and give the user their last message back so they can change it.
+    fn cancel_bot_response(&mut self) -> Result<(), anyhow::Error> {
+        match mem::replace(&mut self.inner, Inner::UsersTurn) {
+            Inner::LoadingBotResponse { pending, .. }
+            | Inner::TakingAWhileToLoadBotResponse { pending, .. } => {
+                debug!(
+                    "cancelling request for {}'s response",
+                    self.env.their_name()
+                );
+                pending.handle.abort();
+            }
+            Inner::BotsTurn | Inner::BotsTurnToRegenerate | Inner::BotsTurnToContinue => {
+                // The request hasn't been sent yet, so there's nothing to abort
+            }
+            Inner::UsersTurn => {
+                debug!("there's no bot response to cancel");
+                return Ok(());
+            }
+            Inner::ReadOnly => {
+                self.inner = Inner::ReadOnly;
+                return Ok(());
+            }
+        }
+
+        // Anything else, like a branch that was switched to, is saved history
+        let unanswered_message = match (self.conversation.last(), self.unanswered_message.take()) {
+            (Some(message), Some(id)) if message.id == id => self.conversation.pop(),
+            _ => None,
+        };
+        if let Some(message) = &unanswered_message {
+            delete_message(&self.conn, message.id)?;
+        }
+        let status = if unanswered_message.is_none() && self.is_waiting_on_bot() {
+            format!(
+                "Cancelled {}'s response (press Ctrl+T to ask again)",
+                self.env.their_name()
+            )
+        } else {
+            format!("Cancelled {}'s response", self.env.their_name())
+        };
+        if let (true, Some(id)) = (self.conversation.is_empty(), self.conversation_id) {
+            // Don't leave an empty conversation behind for `--resume` to find
+            delete_conversation(&self.conn, id)?;
+            self.conversation_id = None;
+        }
+
+        self.frontend_tx
+            .send(Event::ConversationUpdated(self.conversation.clone()))
+            .map_err(|e| {
+                anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
+            })?;
+        self.frontend_tx
+            .send(Event::BotResponseCancelled(
+                unanswered_message.map(|message| message.content),
+            ))
+            .map_err(|e| anyhow::anyhow!("failed to notify frontend of cancellation: {e}"))?;
+        self.frontend_tx
+            .send(Event::StatusUpdated(status))
+            .map_err(|e| anyhow::anyhow!("failed to notify frontend of status update: {e}"))
+    }
+
+    fn receive_bot_response(
+        &mut self,
+        response: Result<Message, anyhow::Error>,
+        kind: ResponseKind,
+        status: String,
+    ) -> Result<(), anyhow::Error> {
+        // Whether the bot responded or not, the user gets to decide what happens next
+        self.inner = Inner::UsersTurn;
+        self.unanswered_message = None;
+        self.failed = response.as_ref().err().map(|_| kind);
+
+        match response {
+            Ok(message) if kind == ResponseKind::Continuation => {
+                let Some(last_message) = self.conversation.last_mut() else {
+                    anyhow::bail!("there's no response to continue");
+                };
+                last_message.continue_with(message);
+                save_continuation(&self.conn, last_message)?;
+                self.frontend_tx
+                    .send(Event::ConversationUpdated(self.conversation.clone()))
+                    .map_err(|e| {
+                        anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
+                    })?;
+                self.frontend_tx
+                    .send(Event::StatusUpdated(status))
+                    .map_err(|e| anyhow::anyhow!("failed to notify frontend of status update: {e}"))
+            }
+            Ok(message) if kind == ResponseKind::Regeneration => {
+                let Some(last_message) = self.conversation.last_mut() else {
+                    anyhow::bail!("there's no response to regenerate");
+                };
+                last_message.add_alternates(message);
+                save_alternates(&self.conn, last_message)?;
+                self.frontend_tx
+                    .send(Event::ConversationUpdated(self.conversation.clone()))
+                    .map_err(|e| {
+                        anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
+                    })?;
+                self.frontend_tx
+                    .send(Event::StatusUpdated(status))
+                    .map_err(|e| anyhow::anyhow!("failed to notify frontend of status update: {e}"))
+            }
+            Ok(message) => {
+                let message = self.save_message(message)?;
+                self.conversation.push(message);
+                self.frontend_tx
+                    .send(Event::ConversationUpdated(self.conversation.clone()))
+                    .map_err(|e| {
+                        anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
+                    })?;
+                self.frontend_tx
+                    .send(Event::StatusUpdated(status))
+                    .map_err(|e| anyhow::anyhow!("failed to notify frontend of status update: {e}"))
+            }
+            Err(e) => {
+                error!(
+                    "failed to fetch response from {}: {e:#}",
+                    self.env.their_name()
+                );
+                if kind == ResponseKind::Regeneration {
+                    // Put back the response that was hidden while it was being regenerated
+                    self.frontend_tx
+                        .send(Event::ConversationUpdated(self.conversation.clone()))
+                        .map_err(|e| {
+                            anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
+                        })?;
+                }
+                self.frontend_tx
+                    .send(Event::BotResponseFailed(format!("{e:#}")))
+                    .map_err(|e| anyhow::anyhow!("failed to notify frontend of failure: {e}"))
+            }
+        }
+    }
+
+    /// Send a new version of one of the user's earlier messages. It starts a new branch of the
+    /// conversation, following the same message the original did, and the bot responds to it.
+    fn edit_message(&mut self, id: u64, content: String) -> Result<(), anyhow::Error> {
+        let position = self
+            .conversation
+            .iter()
+            .position(|m| m.id == id && m.sender == self.env.your_name());
+        let (Some(position), Some(conversation_id), Inner::UsersTurn) =
+            (position, self.conversation_id, &self.inner)
+        else {
+            debug!("can't edit message {id} right now");
+            return Ok(());
+        };
+
+        debug!("editing message {id}");
+        self.conversation.truncate(position);
+        let message = self.save_message(Message {
+            sender: self.env.your_name().to_owned(),
+            content,
+            timestamp: chrono::Utc::now(),
+            id: 0,
+            usage: None,
+            alternates: None,
+            parent: None,
+            siblings: None,
+            sampling: None,
+            truncated: false,
+        })?;
+        // Reloading marks the edit and the original as siblings
+        let messages = get_messages_by_conversation_id(&self.conn, conversation_id)?;
+        self.conversation = branch::branch_to(&messages, message.id);
+        self.inner = Inner::BotsTurn;
+
+        self.frontend_tx
+            .send(Event::ConversationUpdated(self.conversation.clone()))
+            .map_err(|e| anyhow::anyhow!("failed to notify frontend of conversation update: {e}"))
+    }
+
+    /// Show the branch of the conversation through a sibling of the message `id`.
+    fn switch_branch(&mut self, id: u64, forward: bool) -> Result<(), anyhow::Error> {
+        if !matches!(self.inner, Inner::UsersTurn) {
+            debug!("not switching branches while the bot is responding");
+            return Ok(());
+        }
+        let Some(conversation_id) = self.conversation_id else {
+            return Ok(());
+        };
+        let messages = get_messages_by_conversation_id(&self.conn, conversation_id)?;
+        let Some(branch) = branch::switch_branch(&messages, id, forward) else {
+            debug!("message {id} doesn't have any other branches");
+            return Ok(());
+        };
+
+        debug!("switching to the branch through a sibling of message {id}");
+        // A branch whose last message went unanswered gets its answer now
+        if branch
+            .last()
+            .is_some_and(|m| m.sender == self.env.your_name())
+        {
+            self.inner = Inner::BotsTurn;
+        }
+        self.conversation = branch;
+        self.frontend_tx
+            .send(Event::ConversationUpdated(self.conversation.clone()))
+            .map_err(|e| anyhow::anyhow!("failed to notify frontend of conversation update: {e}"))
+    }
+
+    /// Ask the bot for another take on its last response, if it's the user's turn and the bot was
+    /// the last to speak.
+    fn regenerate_bot_response(&mut self) -> Result<(), anyhow::Error> {
+        let is_bots_message = self
+            .conversation
+            .last()
+            .is_some_and(|m| m.sender == self.env.their_name());
+        if !matches!(self.inner, Inner::UsersTurn) || !is_bots_message {
+            debug!("there's no response to regenerate");
+            return Ok(());
+        }
+
+        debug!("regenerating {}'s response", self.env.their_name());
+        self.inner = Inner::BotsTurnToRegenerate;
+        // The new response is streamed in where the old one was
+        self.frontend_tx
+            .send(Event::ConversationUpdated(
+                conversation_so_far(&self.conversation, ResponseKind::Regeneration).to_vec(),
+            ))
+            .map_err(|e| anyhow::anyhow!("failed to notify frontend of conversation update: {e}"))
+    }
+
+    /// Ask the bot to carry on with its last response, if it's the user's turn and the response was
+    /// cut off by the token limit.
+    fn continue_bot_response(&mut self) -> Result<(), anyhow::Error> {
+        let is_truncated = self
+            .conversation
+            .last()
+            .is_some_and(|m| m.sender == self.env.their_name() && m.truncated);
+        if !matches!(self.inner, Inner::UsersTurn) || !is_truncated {
+            debug!("there's no response to continue");
+            return Ok(());
+        }
+
+        debug!("continuing {}'s response", self.env.their_name());
+        // The rest is streamed in below the part that's already there
+        self.inner = Inner::BotsTurnToContinue;
 
         Ok(())
     }
-}
 
-fn create_prompt_from_messages(
-    starting_prompt: &str,
-    messages: &[Message],
-    prompt_context_length: usize,
-) -> String {
-    let mut message_iter = messages.iter();
-    while message_iter.len() > prompt_context_length {
-        // Skip messages until we've limited ourselves to the last <PROMPT_MESSAGES_TO_SEND> messages
-        let _ = message_iter.next();
+    /// Switch the bot's last response to another of its alternates.
+    fn cycle_alternates(&mut self, forward: bool) -> Result<(), anyhow::Error> {
+        if !matches!(self.inner, Inner::UsersTurn) {
+            debug!("not switching alternates while the bot is responding");
+            return Ok(());
+        }
+        let Some(last_message) = self.conversation.last_mut() else {
+            return Ok(());
+        };
+        if !last_message.cycle_alternates(forward) {
+            debug!("the last message doesn't have any alternates");
+            return Ok(());
+        }
+
+        save_alternates(&self.conn, last_message)?;
+        self.frontend_tx
+            .send(Event::ConversationUpdated(self.conversation.clone()))
+            .map_err(|e| anyhow::anyhow!("failed to notify frontend of conversation update: {e}"))
+    }
+
+    /// Write a conversation out as Markdown in the current directory, returning the file's name.
+    fn export_conversation(&self, id: i64) -> Result<String, anyhow::Error> {
+        let path = export::file_name(id, ExportFormat::Markdown);
+        let exported = export::export_conversation(&self.conn, id, ExportFormat::Markdown)?;
+        std::fs::write(&path, exported).with_context(|| format!("failed to write {path}"))?;
+
+        Ok(path)
     }
 
-    let messages_len = message_iter.clone().map(|m| m.content.len()).sum::<usize>();
-    // Really, the final prompt will be longer than this due to also including names and timestamps,
-    // but this is a good starting point.
-    let mut prompt = String::with_capacity(messages_len + starting_prompt.len());
+    /// Switch to a saved conversation, abandoning any response the bot was working on.
+    fn resume_conversation(&mut self, id: i64) -> Result<(), anyhow::Error> {
+        let messages = get_newest_branch(&self.conn, id)?;
+        debug!("resuming conversation {id}");
 
-    if !starting_prompt.is_empty() {
-        prompt.push_str(starting_prompt);
-        prompt.push_str("\n\n")
+        if let Inner::LoadingBotResponse { pending, .. }
+        | Inner::TakingAWhileToLoadBotResponse { pending, .. } = &self.inner
+        {
+            pending.handle.abort();
+        }
+
+        let is_users_turn = match messages.last() {
+            Some(message) => message.sender == self.env.their_name(),
+            None => true,
+        };
+        self.inner = if is_users_turn {
+            Inner::UsersTurn
+        } else {
+            Inner::BotsTurn
+        };
+        self.conversation_id = Some(id);
+        self.conversation = messages;
+        self.unanswered_message = None;
+
+        self.frontend_tx
+            .send(Event::ConversationUpdated(self.conversation.clone()))
+            .map_err(|e| {
+                anyhow::anyhow!("failed to notify frontend of conversation update: {e}")
+            })?;
+        self.frontend_tx
+            .send(Event::StatusUpdated(format!(
+                "Resumed your conversation with {}",
+                self.env.their_name()
+            )))
+            .map_err(|e| anyhow::anyhow!("failed to notify frontend of status update: {e}"))
     }
 
-    for message in message_iter {
-        prompt.push_str(format!("{}:\n{}\n\n", &message.sender, &message.content).as_str());
+    // Whether the branch being shown ends with a message from the user that hasn't been answered
+    fn is_waiting_on_bot(&self) -> bool {
+        self.conversation
+            .last()
+            .is_some_and(|m| m.sender == self.env.your_name())
     }
 
-    prompt
+    /// Write a message to the database, starting the conversation first if this is its first
+    /// message. It follows the last message of the branch being shown. Returns the message with
+    /// the ID it was saved with.
+    fn save_message(&mut self, mut message: Message) -> Result<Message, anyhow::Error> {
+        let conversation_id = match self.conversation_id {
+            Some(id) => id,
+            None => {
+                let id =
+                    insert_conversation(&self.conn, self.env.starting_prompt(), message.timestamp)?;
+                self.conversation_id = Some(id);
+                id
+            }
+        };
+        message.parent = self.conversation.last().map(|m| m.id);
+        message.id = insert_message(&self.conn, conversation_id, &message)?;
+        // Several responses at once are saved as alternates from the start
+        save_alternates(&self.conn, &mut message)?;
+
+        Ok(message)
+    }
+
+    pub async fn quit(self) -> Result<(), anyhow::Error> {
+        // Every message has already been saved, so all that's left is to close the database
+        self.conn
+            .close()
+            .map_err(|(_, e)| e)
+            .context("closing database")
+    }
+}
+
+fn new_provider(env: &Env) -> Result<Box<dyn Provider>, anyhow::Error> {
+    Ok(match env.provider() {
+        ProviderKind::OpenAi => Box::new(OpenAiProvider::new(
+            ApiConfig::new(
+                env.openai_base_url(),
+                env.openai_api_key().map(ToOwned::to_owned),
+                env.openai_organization_id().map(ToOwned::to_owned),
+            )
+            .context("setting up HTTP client")?,
+            RetryPolicy::new(env.retry_max_attempts(), env.retry_base_delay()),
+        )),
+        ProviderKind::Echo => Box::new(EchoProvider),
+        ProviderKind::Scripted => {
+            let path = env.script_path().ok_or_else(|| {
+                anyhow::anyhow!("the scripted provider requires a script, pass one with --script")
+            })?;
+            Box::new(ScriptedProvider::from_file(path)?)
+        }
+    })
+}
+
+// The part of the conversation the bot is responding to. A response that's being regenerated isn't
+// part of it, but one that's being continued is.
+fn conversation_so_far(conversation: &[Message], kind: ResponseKind) -> &[Message] {
+    if kind == ResponseKind::Regeneration {
+        &conversation[..conversation.len().saturating_sub(1)]
+    } else {
+        conversation
+    }
+}
+
+/// Pass any partial responses received since the last tick along to the frontend.
+fn forward_response_progress(
+    pending: &mut PendingResponse,
+    frontend_tx: &EventTx,
+) -> Result<(), anyhow::Error> {
+    while let Ok(progress) = pending.progress_rx.try_recv() {
+        match progress {
+            ResponseProgress::Delta(delta) => {
+                pending.is_streaming = true;
+                frontend_tx
+                    .send(Event::BotResponseDelta(delta))
+                    .map_err(|e| {
+                        anyhow::anyhow!("failed to notify frontend of response delta: {e}")
+                    })?;
+            }
+            ResponseProgress::Retrying {
+                attempt,
+                max_attempts,
+            } => {
+                pending.retrying = Some((attempt, max_attempts));
+                if pending.is_streaming {
+                    pending.is_streaming = false;
+                    frontend_tx.send(Event::BotResponseRestarted).map_err(|e| {
+                        anyhow::anyhow!("failed to notify frontend of restarted response: {e}")
+                    })?;
+                }
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[instrument(skip(rx))]
-fn check_for_bot_response(their_name: &str, rx: &mut mpsc::Receiver<Message>) -> Option<Message> {
+fn check_for_bot_response(
+    their_name: &str,
+    rx: &mut mpsc::Receiver<Result<Message, anyhow::Error>>,
+) -> Option<Result<Message, anyhow::Error>> {
     match rx.try_recv() {
-        Ok(message) => {
+        Ok(Ok(message)) => {
             debug!("received response from {their_name}",);
             trace!(
                 message.timestamp = message.timestamp.to_rfc2822().as_str(),
@@ -306,16 +918,17 @@ fn check_for_bot_response(their_name: &str, rx: &mut mpsc::Receiver<Message>) ->
                 "bot sent message"
             );
 
-            Some(message)
+            Some(Ok(message))
         }
+        Ok(Err(e)) => Some(Err(e)),
         Err(e) => match e {
             mpsc::error::TryRecvError::Empty => {
                 trace!("no response from {their_name} yet");
                 None
             }
-            mpsc::error::TryRecvError::Disconnected => {
-                unreachable!("The request task can't close this mpsc before sending the response")
-            }
+            mpsc::error::TryRecvError::Disconnected => Some(Err(anyhow::anyhow!(
+                "the request for {their_name}'s response stopped without responding"
+            ))),
         },
     }
 }
@@ -1,11 +1,17 @@
 use anyhow::Context;
+use clap::ValueEnum;
 use std::{
     env,
     path::{Path, PathBuf},
     time::Duration,
 };
 
-use crate::Args;
+use crate::{
+    openai_api::DEFAULT_BASE_URL,
+    provider::ProviderKind,
+    sampling::{self, SamplingParameters},
+    Args,
+};
 
 const DEFAULT_PROMPT: &str = "The following is a conversation that 'User' is having with an AI assistant named 'Bot'. The assistant is helpful, creative, clever, and very friendly.";
 const DEFAULT_YOUR_NAME: &str = "User";
@@ -13,8 +19,12 @@ const DEFAULT_THEIR_NAME: &str = "Bot";
 const DEFAULT_MODEL_NAME: &str = "text-davinci-003";
 const DEFAULT_TOKEN_LIMIT: u32 = 100;
 const DEFAULT_EXPECTED_RESPONSE_TIME: Duration = Duration::from_secs(5);
-const DEFAULT_PROMPT_CONTEXT_LENGTH: usize = 5;
 const DEFAULT_DB_PATH: &str = "chatbot.db";
+const DEFAULT_STREAM_RESPONSES: bool = true;
+const DEFAULT_ALTERNATES: u32 = 1;
+const DEFAULT_PROVIDER: ProviderKind = ProviderKind::OpenAi;
+const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
 
 pub struct Env {
     your_name: String,
@@ -22,10 +32,20 @@ pub struct Env {
     starting_prompt: String,
     openai_model_name: String,
     expected_response_time: Duration,
-    prompt_context_length: usize,
+    prompt_context_length: Option<usize>,
     database_file_path: PathBuf,
     user_input_poll_duration: Duration,
     token_limit: u32,
+    stream_responses: bool,
+    alternates: u32,
+    sampling: SamplingParameters,
+    provider: ProviderKind,
+    script_path: Option<PathBuf>,
+    openai_base_url: String,
+    openai_api_key: Option<String>,
+    openai_organization_id: Option<String>,
+    retry_max_attempts: u32,
+    retry_base_delay: Duration,
 }
 
 impl Env {
@@ -58,14 +78,12 @@ impl Env {
                     .map(Duration::from_millis)
             })
             .unwrap_or(DEFAULT_EXPECTED_RESPONSE_TIME);
-        let prompt_context_length = args
-            .prompt_context_length()
-            .or_else(|| {
-                env::var("PROMPT_CONTEXT_LENGTH")
-                    .ok()
-                    .and_then(|s| s.parse().ok())
-            })
-            .unwrap_or(DEFAULT_PROMPT_CONTEXT_LENGTH);
+        // Without a limit, as many messages as fit in the model's context window are sent
+        let prompt_context_length = args.prompt_context_length().or_else(|| {
+            env::var("PROMPT_CONTEXT_LENGTH")
+                .ok()
+                .and_then(|s| s.parse().ok())
+        });
         let database_file_path = args.db_path().map(PathBuf::from).unwrap_or_else(|| {
             env::var("DATABASE_FILE_PATH")
                 .map(PathBuf::from)
@@ -83,6 +101,100 @@ impl Env {
             .token_limit()
             .or_else(|| env::var("TOKEN_LIMIT").ok().and_then(|s| s.parse().ok()))
             .unwrap_or(DEFAULT_TOKEN_LIMIT);
+        let stream_responses = args
+            .no_stream()
+            .then_some(false)
+            .or_else(|| {
+                env::var("STREAM_RESPONSES")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+            })
+            .unwrap_or(DEFAULT_STREAM_RESPONSES);
+        let alternates = args
+            .alternates()
+            .or_else(|| env::var("ALTERNATES").ok().and_then(|s| s.parse().ok()))
+            .unwrap_or(DEFAULT_ALTERNATES)
+            .max(1);
+        let sampling = SamplingParameters {
+            // The bot has always answered with a temperature of 0 unless told otherwise
+            temperature: args
+                .temperature()
+                .or_else(|| env::var("TEMPERATURE").ok().and_then(|s| s.parse().ok()))
+                .or(Some(0.0)),
+            top_p: args
+                .top_p()
+                .or_else(|| env::var("TOP_P").ok().and_then(|s| s.parse().ok())),
+            presence_penalty: args.presence_penalty().or_else(|| {
+                env::var("PRESENCE_PENALTY")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+            }),
+            frequency_penalty: args.frequency_penalty().or_else(|| {
+                env::var("FREQUENCY_PENALTY")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+            }),
+            stop: if args.stop_sequences().is_empty() {
+                env::var("STOP_SEQUENCES")
+                    .map(|list| sampling::parse_stop_sequences(&list))
+                    .unwrap_or_default()
+            } else {
+                args.stop_sequences()
+                    .iter()
+                    .map(|sequence| sequence.replace("\\n", "\n"))
+                    .collect()
+            },
+            seed: args
+                .seed()
+                .or_else(|| env::var("SEED").ok().and_then(|s| s.parse().ok())),
+        };
+        if args.talks_to_bot() {
+            sampling.validate()?;
+        }
+        let provider = args
+            .provider()
+            .or_else(|| {
+                env::var("PROVIDER")
+                    .ok()
+                    .and_then(|s| ValueEnum::from_str(&s, true).ok())
+            })
+            .unwrap_or(DEFAULT_PROVIDER);
+        let script_path = args
+            .script()
+            .map(PathBuf::from)
+            .or_else(|| env::var("SCRIPT_FILE_PATH").ok().map(PathBuf::from));
+        let openai_base_url = args
+            .base_url()
+            .map(ToOwned::to_owned)
+            .or_else(|| env::var("OPENAI_BASE_URL").ok())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_owned());
+        let openai_api_key = env::var("OPENAI_API_KEY").ok().filter(|s| !s.is_empty());
+        let openai_organization_id = env::var("OPENAI_ORGANIZATION_ID")
+            .ok()
+            .filter(|s| !s.is_empty());
+
+        let retry_max_attempts = env::var("RETRY_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_RETRY_MAX_ATTEMPTS);
+        let retry_base_delay = env::var("RETRY_BASE_DELAY")
+            .context("checking for retry_base_delay in env")
+            .and_then(|t| {
+                t.parse()
+                    .context("parsing retry_base_delay from env")
+                    .map(Duration::from_millis)
+            })
+            .unwrap_or(DEFAULT_RETRY_BASE_DELAY);
+
+        // Local servers usually don't need a key, but OpenAI itself always does. Better to find
+        // out now than after the user has typed their first message.
+        if args.talks_to_bot()
+            && provider == ProviderKind::OpenAi
+            && openai_api_key.is_none()
+            && openai_base_url == DEFAULT_BASE_URL
+        {
+            anyhow::bail!("OPENAI_API_KEY must be set to talk to OpenAI");
+        }
 
         Ok(Self {
             your_name,
@@ -94,6 +206,16 @@ impl Env {
             database_file_path,
             user_input_poll_duration,
             token_limit,
+            stream_responses,
+            alternates,
+            sampling,
+            provider,
+            script_path,
+            openai_base_url,
+            openai_api_key,
+            openai_organization_id,
+            retry_max_attempts,
+            retry_base_delay,
         })
     }
 
@@ -117,7 +239,7 @@ impl Env {
         self.expected_response_time
     }
 
-    pub fn prompt_context_length(&self) -> usize {
+    pub fn prompt_context_length(&self) -> Option<usize> {
         self.prompt_context_length
     }
 
@@ -133,4 +255,49 @@ impl Env {
     pub fn token_limit(&self) -> u32 {
         self.token_limit
     }
+
+    // When true, the bot's responses are requested as a stream and displayed as they're written.
+    pub fn stream_responses(&self) -> bool {
+        self.stream_responses
+    }
+
+    // How many responses to ask for at once when regenerating the bot's response.
+    pub fn alternates(&self) -> u32 {
+        self.alternates
+    }
+
+    // What the bot's responses are asked for with, until they're changed in the settings.
+    pub fn sampling(&self) -> &SamplingParameters {
+        &self.sampling
+    }
+
+    pub fn provider(&self) -> ProviderKind {
+        self.provider
+    }
+
+    pub fn script_path(&self) -> Option<&Path> {
+        self.script_path.as_deref()
+    }
+
+    pub fn openai_base_url(&self) -> &str {
+        &self.openai_base_url
+    }
+
+    pub fn openai_api_key(&self) -> Option<&str> {
+        self.openai_api_key.as_deref()
+    }
+
+    pub fn openai_organization_id(&self) -> Option<&str> {
+        self.openai_organization_id.as_deref()
+    }
+
+    // The most times a failed request for the bot's response will be attempted, including the first.
+    pub fn retry_max_attempts(&self) -> u32 {
+        self.retry_max_attempts
+    }
+
+    // How long to wait before the first retry. The wait doubles with every retry after that.
+    pub fn retry_base_delay(&self) -> Duration {
+        self.retry_base_delay
+    }
 }
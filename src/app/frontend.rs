@@ -1,7 +1,18 @@
-use crate::message::Message;
+mod browser;
+mod markdown;
+mod scroll;
+mod search;
+mod settings;
+
+use crate::{
+    message::{Message, TokenUsage},
+    openai_api::pricing,
+    sampling::SamplingParameters,
+    Args,
+};
 use anyhow::Context;
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -17,19 +28,73 @@ use tui::{
     Frame,
 };
 use tui::{backend::CrosstermBackend, Terminal};
-use tui_textarea::{Input, Key, TextArea};
+use tui_textarea::{CursorMove, Input, Key, TextArea};
 
 use super::{env::Env, Event, EventRx, EventTx};
+use browser::ConversationBrowser;
+use markdown::MarkdownRenderer;
+use scroll::ConversationScroll;
+use search::SearchOverlay;
+use settings::SettingsPanel;
+
+const BROWSER_HELP: &str = "Enter: resume · V: view · Esc: back to chat";
+const SEARCH_HELP: &str = "Enter: search, or open the selected message · Esc: back to chat";
+const SETTINGS_HELP: &str = "↑/↓: move between settings · Enter: save · Esc: cancel";
+const VIEWER_HELP: &str =
+    "Viewing a saved conversation · Alt+E: export · Esc: back to conversations";
+const SEARCH_RESULT_HELP: &str =
+    "Viewing a saved conversation · Alt+E: export · Esc: back to search results";
+const READ_ONLY_HELP: &str = "Viewing a saved conversation · Alt+E: export · Esc: quit";
+const SELECTED_HELP: &str =
+    "Alt+↑/↓: select a message · Alt+←/→: switch branches · Enter: edit · Esc: done";
+const EDITING_HELP: &str = "Editing your message · Enter: send it as a new branch · Esc: cancel";
 
 enum Inner {
     AwaitingUserInput,
     AwaitingBotResponse,
 }
 
+// What's on screen. The chat carries on in the background while browsing.
+enum Mode {
+    Chatting,
+    Browsing(ConversationBrowser),
+    Searching(Box<SearchOverlay>),
+    Settings(Box<SettingsPanel>),
+    // Wherever the conversation was opened from is kept around so that going back to it doesn't
+    // lose the user's place. There's nothing to go back to when the app was started with `--view`.
+    Viewing {
+        conversation_id: i64,
+        conversation: Vec<Message>,
+        // The message to scroll to and highlight, when the conversation was opened from a search
+        focused_message: Option<u64>,
+        // `None` until the conversation is first drawn, which is when the focused message is
+        // scrolled to
+        scroll: Option<ConversationScroll>,
+        previous: Option<Box<Mode>>,
+    },
+}
+
 // TODO Can these use Cows instead?
 struct WidgetState {
     conversation: Vec<Message>,
+    // The part of the bot's response that has been streamed in so far
+    pending_response: Option<String>,
+    // Set while the bot carries on with its last message, so what's streamed in is shown as part of
+    // that message
+    continuing: bool,
     status: String,
+    // Set when the bot failed to respond, until the user retries or moves on
+    error: Option<String>,
+    // Shown in place of the status until the next key press
+    notice: Option<String>,
+    // The position in the conversation of the message picked out with Alt+Up and Alt+Down
+    selected: Option<usize>,
+    // Set when the selection moves, until the selected message has been scrolled to
+    reveal_selected: bool,
+    // The ID of the message whose new version is in the textarea
+    editing: Option<u64>,
+    scroll: ConversationScroll,
+    markdown: MarkdownRenderer,
     textarea: TextArea<'static>,
 }
 
@@ -40,6 +105,9 @@ pub struct FrontendState {
     widget_state: WidgetState,
     terminal: Terminal<CrosstermBackend<io::Stdout>>,
     inner: Inner,
+    mode: Mode,
+    // The sampling parameters the backend was last told to use, for the settings to start from
+    sampling: SamplingParameters,
     env: Arc<Env>,
 }
 
@@ -49,16 +117,33 @@ impl FrontendState {
         backend_tx: EventTx,
         app_tx: EventTx,
         env: Arc<Env>,
+        args: &Args,
     ) -> Result<Self, anyhow::Error> {
         trace!("setting up terminal");
 
         enable_raw_mode()?;
         let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+        // Bracketed paste makes pasted text arrive all at once, instead of as keypresses that would
+        // send the message at the first newline
+        execute!(
+            stdout,
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableBracketedPaste
+        )?;
         let backend = CrosstermBackend::new(stdout);
         let widget_state = WidgetState {
             conversation: Vec::new(),
+            pending_response: None,
+            continuing: false,
             status: "loading the chatbot...".to_owned(),
+            error: None,
+            notice: None,
+            selected: None,
+            reveal_selected: false,
+            editing: None,
+            scroll: ConversationScroll::default(),
+            markdown: MarkdownRenderer::default(),
             textarea: TextArea::default(),
         };
 
@@ -69,6 +154,18 @@ impl FrontendState {
             widget_state,
             terminal: Terminal::new(backend)?,
             inner: Inner::AwaitingUserInput,
+            mode: match args.view() {
+                // The backend will send the conversation once it's loaded
+                Some(id) => Mode::Viewing {
+                    conversation_id: id,
+                    conversation: Vec::new(),
+                    focused_message: None,
+                    scroll: None,
+                    previous: None,
+                },
+                None => Mode::Chatting,
+            },
+            sampling: env.sampling().clone(),
             env,
         })
     }
@@ -79,35 +176,268 @@ impl FrontendState {
         while let Ok(true) = crossterm::event::poll(self.env.user_input_poll_duration()) {
             // This can potentially block although it shouldn't since I'm polling first. Still, I
             // feel weird about this and wonder if there's a better way.
-            match crossterm::event::read()?.into() {
+            let event = crossterm::event::read()?;
+            self.widget_state.notice = None;
+            if let crossterm::event::Event::Paste(text) = event {
+                match &mut self.mode {
+                    Mode::Chatting => paste_into(&mut self.widget_state.textarea, &text),
+                    Mode::Searching(search) => search.paste(&text),
+                    Mode::Settings(settings) => settings.paste(&text),
+                    _ => trace!("ignoring paste outside of the chat"),
+                }
+                continue;
+            }
+
+            let input = event.into();
+            if !matches!(self.mode, Mode::Chatting) {
+                self.handle_overlay_input(input)?;
+                continue;
+            }
+            let composing = !self.widget_state.textarea.is_empty();
+            if self.widget_state.scroll.handle_input(&input, composing) {
+                continue;
+            }
+
+            match input {
                 Input { key: Key::Esc, .. } => {
-                    self.app_tx
-                        .send(Event::Quit)
-                        .map_err(|e| anyhow::anyhow!("failed to send Quit event to app: {}", e))?;
+                    if self.widget_state.editing.take().is_some() {
+                        debug!("cancelling edit");
+                        self.widget_state.textarea = TextArea::default();
+                    } else if self.widget_state.selected.take().is_none() {
+                        self.app_tx.send(Event::Quit).map_err(|e| {
+                            anyhow::anyhow!("failed to send Quit event to app: {}", e)
+                        })?;
+                    }
+                }
+                Input {
+                    key: Key::Up,
+                    ctrl: false,
+                    alt: true,
+                } => {
+                    let last = self.widget_state.conversation.len().checked_sub(1);
+                    self.widget_state.selected = match self.widget_state.selected {
+                        Some(selected) => Some(selected.saturating_sub(1)),
+                        None => last,
+                    };
+                    self.widget_state.reveal_selected = true;
+                }
+                Input {
+                    key: Key::Down,
+                    ctrl: false,
+                    alt: true,
+                } => {
+                    let len = self.widget_state.conversation.len();
+                    // Moving down past the last message is the same as being done
+                    self.widget_state.selected = self
+                        .widget_state
+                        .selected
+                        .map(|selected| selected + 1)
+                        .filter(|selected| *selected < len);
+                    self.widget_state.reveal_selected = true;
+                }
+                // Enter sends the message, so this is how to start a new line. Most terminals
+                // can't tell Shift+Enter apart from Enter.
+                Input {
+                    key: Key::Enter,
+                    alt: true,
+                    ..
+                } => {
+                    self.widget_state.textarea.insert_newline();
                 }
                 Input {
                     key: Key::Enter, ..
                 } => {
-                    if self.widget_state.textarea.is_empty() {
+                    let is_blank = self
+                        .widget_state
+                        .textarea
+                        .lines()
+                        .iter()
+                        .all(|line| line.trim().is_empty());
+                    if let Some(selected) = self.widget_state.selected {
+                        self.start_editing(selected, is_blank);
+                    } else if is_blank {
                         debug!("user attempted to send message but it's empty");
                     } else if matches!(self.inner, Inner::AwaitingUserInput) {
                         debug!("sending message to backend after receiving Enter keypress");
+                        self.widget_state.scroll.scroll_to_bottom();
                         // Clear the textarea by replacing it with a new one.
                         let content = std::mem::take(&mut self.widget_state.textarea)
                             .into_lines()
-                            .remove(0);
+                            .join("\n");
+                        let event = match self.widget_state.editing.take() {
+                            Some(id) => Event::EditMessage(id, content),
+                            None => Event::UserMessage(content),
+                        };
+                        self.backend_tx.send(event).map_err(|e| {
+                            anyhow::anyhow!("failed to send message to backend: {}", e)
+                        })?;
+                    } else {
+                        debug!("user attempted to send message but it's not their turn");
+                    }
+                }
+                Input {
+                    key: Key::Char('t'),
+                    ctrl: true,
+                    alt: false,
+                } => {
+                    let is_unanswered = self
+                        .widget_state
+                        .conversation
+                        .last()
+                        .is_some_and(|m| m.sender == self.env.your_name());
+                    if (self.widget_state.error.is_some() || is_unanswered)
+                        && matches!(self.inner, Inner::AwaitingUserInput)
+                    {
+                        debug!("asking backend to retry the bot's response");
+                        self.widget_state.error = None;
+                        // Only a continuation is retried without taking the bot's message away
+                        self.widget_state.continuing = self
+                            .widget_state
+                            .conversation
+                            .last()
+                            .is_some_and(|m| m.sender == self.env.their_name() && m.truncated);
+                        self.inner = Inner::AwaitingBotResponse;
+                        self.backend_tx.send(Event::RetryBotResponse).map_err(|e| {
+                            anyhow::anyhow!(
+                                "failed to send RetryBotResponse event to backend: {}",
+                                e
+                            )
+                        })?;
+                    } else {
+                        debug!("user attempted to retry but there's nothing to retry");
+                    }
+                }
+                Input {
+                    key: Key::Char('c'),
+                    ctrl: true,
+                    alt: false,
+                } if matches!(self.inner, Inner::AwaitingBotResponse) => {
+                    debug!("asking backend to cancel the bot's response");
+                    self.backend_tx
+                        .send(Event::CancelBotResponse)
+                        .map_err(|e| {
+                            anyhow::anyhow!(
+                                "failed to send CancelBotResponse event to backend: {}",
+                                e
+                            )
+                        })?;
+                }
+                Input {
+                    key: Key::Char('o'),
+                    ctrl: true,
+                    alt: false,
+                } => {
+                    debug!("opening the conversation browser");
+                    self.mode = Mode::Browsing(ConversationBrowser::default());
+                    self.backend_tx
+                        .send(Event::ListConversations)
+                        .map_err(|e| {
+                            anyhow::anyhow!(
+                                "failed to send ListConversations event to backend: {}",
+                                e
+                            )
+                        })?;
+                }
+                Input {
+                    key: Key::Char('s'),
+                    ctrl: true,
+                    alt: false,
+                } => {
+                    debug!("opening search");
+                    self.mode = Mode::Searching(Box::default());
+                }
+                Input {
+                    key: Key::Char('g'),
+                    ctrl: true,
+                    alt: false,
+                } => {
+                    debug!("opening the settings");
+                    self.mode = Mode::Settings(Box::new(SettingsPanel::new(&self.sampling)));
+                }
+                Input {
+                    key: Key::Char('e'),
+                    ctrl: false,
+                    alt: true,
+                } => {
+                    self.export_conversation(None)?;
+                }
+                Input {
+                    key: Key::Char('r'),
+                    ctrl: false,
+                    alt: true,
+                } => {
+                    let is_bots_message = self
+                        .widget_state
+                        .conversation
+                        .last()
+                        .is_some_and(|m| m.sender == self.env.their_name());
+                    if is_bots_message && matches!(self.inner, Inner::AwaitingUserInput) {
+                        debug!("asking backend to regenerate the bot's response");
+                        self.widget_state.error = None;
+                        self.widget_state.scroll.scroll_to_bottom();
+                        self.inner = Inner::AwaitingBotResponse;
                         self.backend_tx
-                            .send(Event::UserMessage(content))
+                            .send(Event::RegenerateBotResponse)
                             .map_err(|e| {
                                 anyhow::anyhow!(
-                                    "failed to send UserMessage event to backend: {}",
+                                    "failed to send RegenerateBotResponse event to backend: {}",
                                     e
                                 )
                             })?;
                     } else {
-                        debug!("user attempted to send message but it's not their turn");
+                        debug!("user attempted to regenerate but there's nothing to regenerate");
+                    }
+                }
+                Input {
+                    key: Key::Char('c'),
+                    ctrl: false,
+                    alt: true,
+                } => {
+                    let is_truncated = self
+                        .widget_state
+                        .conversation
+                        .last()
+                        .is_some_and(|m| m.sender == self.env.their_name() && m.truncated);
+                    if is_truncated && matches!(self.inner, Inner::AwaitingUserInput) {
+                        debug!("asking backend to continue the bot's response");
+                        self.widget_state.error = None;
+                        self.widget_state.continuing = true;
+                        self.widget_state.scroll.scroll_to_bottom();
+                        self.inner = Inner::AwaitingBotResponse;
+                        self.backend_tx
+                            .send(Event::ContinueBotResponse)
+                            .map_err(|e| {
+                                anyhow::anyhow!(
+                                    "failed to send ContinueBotResponse event to backend: {}",
+                                    e
+                                )
+                            })?;
+                    } else {
+                        debug!("user attempted to continue but there's nothing to continue");
                     }
                 }
+                Input {
+                    key: key @ (Key::Left | Key::Right),
+                    ctrl: false,
+                    alt: true,
+                } => {
+                    // With a message selected, these switch between its branches. Otherwise they
+                    // switch between the bot's alternate responses.
+                    let forward = matches!(key, Key::Right);
+                    let selected = self
+                        .widget_state
+                        .selected
+                        .and_then(|selected| self.widget_state.conversation.get(selected));
+                    let event = match (selected, forward) {
+                        (Some(message), false) => Event::PreviousBranch(message.id),
+                        (Some(message), true) => Event::NextBranch(message.id),
+                        (None, false) => Event::PreviousAlternate,
+                        (None, true) => Event::NextAlternate,
+                    };
+                    self.backend_tx.send(event).map_err(|e| {
+                        anyhow::anyhow!("failed to send switching event to backend: {}", e)
+                    })?;
+                }
                 // Ignore these keyboard shortcuts
                 i @ Input {
                     key: Key::Char('m'),
@@ -144,10 +474,106 @@ impl FrontendState {
                             }
                         }
 
+                        // Switching branches can leave the selection or the message being edited
+                        // behind
+                        let len = conversation.len();
+                        self.widget_state.selected = self
+                            .widget_state
+                            .selected
+                            .filter(|selected| *selected < len);
+                        self.widget_state.editing = self
+                            .widget_state
+                            .editing
+                            .filter(|id| conversation.iter().any(|m| m.id == *id));
+
                         self.widget_state.conversation = conversation;
+                        // Any response that was being streamed is now part of the conversation
+                        self.widget_state.pending_response = None;
+                        self.widget_state.continuing = false;
+                    }
+                    Event::BotResponseDelta(delta) => {
+                        self.widget_state
+                            .pending_response
+                            .get_or_insert_with(String::new)
+                            .push_str(&delta);
+                    }
+                    Event::BotResponseRestarted => {
+                        self.widget_state.pending_response = None;
+                    }
+                    Event::BotResponseFailed(error) => {
+                        // The user can retry, or just keep talking
+                        self.inner = Inner::AwaitingUserInput;
+                        self.widget_state.pending_response = None;
+                        self.widget_state.continuing = false;
+                        self.widget_state.error = Some(error);
+                    }
+                    Event::BotResponseCancelled(unanswered_message) => {
+                        // A message from an earlier turn stays unanswered, but it's still the
+                        // user's turn
+                        self.inner = Inner::AwaitingUserInput;
+                        self.widget_state.pending_response = None;
+                        self.widget_state.continuing = false;
+                        if let Some(content) = unanswered_message {
+                            // Put the message back where it came from, ahead of anything the user
+                            // typed while waiting.
+                            let textarea = std::mem::take(&mut self.widget_state.textarea);
+                            let mut lines: Vec<String> =
+                                content.lines().map(ToOwned::to_owned).collect();
+                            if !textarea.is_empty() {
+                                lines.extend(textarea.into_lines());
+                            }
+
+                            self.widget_state.textarea = TextArea::new(lines);
+                            self.widget_state.textarea.move_cursor(CursorMove::Bottom);
+                            self.widget_state.textarea.move_cursor(CursorMove::End);
+                        }
                     }
                     Event::StatusUpdated(status) => {
                         self.widget_state.status = status;
+                        self.widget_state.error = None;
+                    }
+                    Event::ConversationsListed(conversations) => {
+                        if let Mode::Browsing(browser) = &mut self.mode {
+                            browser.set_conversations(conversations);
+                        }
+                    }
+                    Event::SearchResults(hits) => {
+                        if let Mode::Searching(search) = &mut self.mode {
+                            search.set_hits(hits);
+                        }
+                    }
+                    Event::ConversationExported(notice) => {
+                        self.widget_state.notice = Some(notice);
+                    }
+                    Event::ConversationViewed(conversation_id, conversation) => {
+                        self.mode = match std::mem::replace(&mut self.mode, Mode::Chatting) {
+                            previous @ (Mode::Browsing(_) | Mode::Searching(_)) => {
+                                let focused_message = match &previous {
+                                    Mode::Searching(search) => {
+                                        search.selected().map(|hit| hit.message_id)
+                                    }
+                                    _ => None,
+                                };
+
+                                Mode::Viewing {
+                                    conversation_id,
+                                    conversation,
+                                    focused_message,
+                                    scroll: None,
+                                    previous: Some(Box::new(previous)),
+                                }
+                            }
+                            // Started with `--view`
+                            Mode::Viewing { previous: None, .. } => Mode::Viewing {
+                                conversation_id,
+                                conversation,
+                                focused_message: None,
+                                scroll: None,
+                                previous: None,
+                            },
+                            // The user went back to chatting while the conversation was loading
+                            mode => mode,
+                        };
                     }
                     _ => {}
                 },
@@ -168,6 +594,50 @@ impl FrontendState {
             .draw(|f| {
                 let chunks = build_layout_chunks(f);
 
+                let notice = self.widget_state.notice.as_deref();
+                match &mut self.mode {
+                    Mode::Browsing(browser) => {
+                        browser.render(f, chunks[0].union(chunks[1]));
+                        f.render_widget(build_status_widget(Cow::Borrowed(BROWSER_HELP)), chunks[2]);
+                        return;
+                    }
+                    Mode::Searching(search) => {
+                        search.render(f, chunks[0].union(chunks[1]));
+                        f.render_widget(build_status_widget(Cow::Borrowed(SEARCH_HELP)), chunks[2]);
+                        return;
+                    }
+                    Mode::Settings(settings) => {
+                        settings.render(f, chunks[0].union(chunks[1]));
+                        f.render_widget(build_status_widget(Cow::Borrowed(SETTINGS_HELP)), chunks[2]);
+                        return;
+                    }
+                    Mode::Viewing { conversation, focused_message, scroll, previous, .. } => {
+                        let area = chunks[0].union(chunks[1]);
+                        let markdown = &mut self.widget_state.markdown;
+                        let scroll = scroll.get_or_insert_with(|| match focused_message {
+                            // The scrollbar takes up a column
+                            Some(id) => ConversationScroll::at_line(lines_before_message(
+                                conversation,
+                                *id,
+                                area.width.saturating_sub(1),
+                                markdown,
+                            )),
+                            None => ConversationScroll::default(),
+                        });
+                        let entries = build_conversation_entries(conversation, *focused_message, markdown);
+                        scroll.render(f, area, entries);
+
+                        let help = notice.unwrap_or(match previous.as_deref() {
+                            Some(Mode::Searching(_)) => SEARCH_RESULT_HELP,
+                            Some(_) => VIEWER_HELP,
+                            None => READ_ONLY_HELP,
+                        });
+                        f.render_widget(build_status_widget(Cow::Borrowed(help)), chunks[2]);
+                        return;
+                    }
+                    Mode::Chatting => {}
+                }
+
                 // TODO break this up into smaller functions
                 if self.widget_state.conversation.is_empty() {
                     let p = Paragraph::new(Span::styled(
@@ -182,50 +652,110 @@ impl FrontendState {
 
                     f.render_widget(p, chunks[0]);
                 } else {
-                    let entries: Vec<_> = self.widget_state.conversation
-                        .iter()
-                        .flat_map(|m| {
-                            [
-                                Spans::from(vec![
-                                    Span::styled(&m.sender, Style::default().add_modifier(Modifier::BOLD)),
-                                    Span::raw(": "),
-                                    Span::styled(
-                                        m.timestamp.to_rfc2822(),
-                                        Style::default()
-                                            .fg(Color::Gray)
-                                            .add_modifier(Modifier::ITALIC),
-                                        ),
-                                ]),
-                                Spans::from(Span::raw(&m.content)),
-                                // empty `Spans` to add a newline
-                                Spans::default(),
-                            ]
-                        })
-                        .collect();
-
-                    let conversation_length = entries.len() as u16;
-                    let bottom_of_conversation_block = chunks[0].bottom();
-
-                    let scroll_offset = if bottom_of_conversation_block < conversation_length {
-                        conversation_length - bottom_of_conversation_block + 1
-                    } else {
-                        0
+                    let conversation = &self.widget_state.conversation;
+                    let focused_message = self
+                        .widget_state
+                        .selected
+                        .and_then(|selected| conversation.get(selected))
+                        .map(|m| m.id)
+                        .or(self.widget_state.editing);
+                    if let (Some(id), true) = (focused_message, self.widget_state.reveal_selected) {
+                        // The scrollbar takes up a column
+                        let line = lines_before_message(
+                            conversation,
+                            id,
+                            chunks[0].width.saturating_sub(1),
+                            &mut self.widget_state.markdown,
+                        );
+                        self.widget_state.scroll.scroll_into_view(line);
+                    }
+                    self.widget_state.reveal_selected = false;
+
+                    // The rest of a message that's being continued is shown as part of it
+                    let continued = match (
+                        &self.widget_state.pending_response,
+                        conversation.split_last(),
+                    ) {
+                        (Some(pending_response), Some((last, before)))
+                            if self.widget_state.continuing =>
+                        {
+                            Some((last, before, pending_response))
+                        }
+                        _ => None,
                     };
+                    let mut entries = build_conversation_entries(
+                        continued.map_or(conversation, |(_, before, _)| before),
+                        focused_message,
+                        &mut self.widget_state.markdown,
+                    );
 
-                    let conversation = Paragraph::new(entries)
-                    // TODO allow users to  scroll the conversation
-                    // This will scroll down to the latest message in the conversation.
-                    .scroll((scroll_offset, 0))
-                    .wrap(Wrap { trim: false })
-                    .block(Block::default().borders(Borders::BOTTOM));
+                    if let Some((last, _, pending_response)) = continued {
+                        let mut header = message_header(last, focused_message);
+                        // In place of the note that it was cut off
+                        header.0.pop();
+                        header.0.push(Span::styled(
+                            " · typing...",
+                            Style::default()
+                                .fg(Color::Gray)
+                                .add_modifier(Modifier::ITALIC),
+                        ));
+                        entries.push(header);
+                        entries.extend(
+                            self.widget_state
+                                .markdown
+                                .render_uncached(&format!("{}{pending_response}", last.content)),
+                        );
+                        entries.push(Spans::default());
+                    } else if let Some(pending_response) = &self.widget_state.pending_response {
+                        entries.extend([
+                            Spans::from(vec![
+                                Span::styled(self.env.their_name(), Style::default().add_modifier(Modifier::BOLD)),
+                                Span::raw(": "),
+                                Span::styled(
+                                    "typing...",
+                                    Style::default()
+                                        .fg(Color::Gray)
+                                        .add_modifier(Modifier::ITALIC),
+                                    ),
+                            ]),
+                        ]);
+                        entries.extend(
+                            self.widget_state
+                                .markdown
+                                .render_uncached(pending_response.trim_start()),
+                        );
+                    }
 
-                    f.render_widget(conversation, chunks[0]);
+                    self.widget_state.scroll.render(f, chunks[0], entries);
                 };
 
                 f.render_widget(self.widget_state.textarea.widget(), chunks[1]);
 
-                let status_widget = build_status_widget(self.widget_state.status.as_str().into());
-                f.render_widget(status_widget, chunks[2]);
+                let status_chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+                    .split(chunks[2]);
+                let usage_widget =
+                    build_usage_widget(&self.widget_state.conversation, self.env.openai_model_name());
+                f.render_widget(usage_widget, status_chunks[0]);
+
+                if let Some(error) = &self.widget_state.error {
+                    let status_widget = build_error_widget(error);
+                    f.render_widget(status_widget, status_chunks[1]);
+                } else {
+                    let status = match (notice, &self.inner) {
+                        (Some(notice), _) => Cow::Borrowed(notice),
+                        _ if self.widget_state.editing.is_some() => Cow::Borrowed(EDITING_HELP),
+                        _ if self.widget_state.selected.is_some() => Cow::Borrowed(SELECTED_HELP),
+                        (None, Inner::AwaitingBotResponse) => Cow::Owned(format!(
+                            "{} (press Ctrl+C to cancel)",
+                            self.widget_state.status
+                        )),
+                        (None, Inner::AwaitingUserInput) => Cow::Borrowed(self.widget_state.status.as_str()),
+                    };
+                    let status_widget = build_status_widget(status);
+                    f.render_widget(status_widget, status_chunks[1]);
+                }
             })
             .map(|_| ())
             .context("failed to draw to terminal")?;
@@ -233,6 +763,182 @@ impl FrontendState {
         Ok(())
     }
 
+    // Handles input for everything other than the chat
+    fn handle_overlay_input(&mut self, input: Input) -> Result<(), anyhow::Error> {
+        if let Mode::Viewing {
+            scroll: Some(scroll),
+            ..
+        } = &mut self.mode
+        {
+            if scroll.handle_input(&input, false) {
+                return Ok(());
+            }
+        }
+
+        match (&mut self.mode, input) {
+            (
+                Mode::Browsing(_) | Mode::Searching(_) | Mode::Settings(_),
+                Input { key: Key::Esc, .. },
+            ) => {
+                self.mode = Mode::Chatting;
+            }
+            (Mode::Browsing(browser), Input { key: Key::Up, .. }) => browser.select_previous(),
+            (Mode::Browsing(browser), Input { key: Key::Down, .. }) => browser.select_next(),
+            (
+                Mode::Browsing(browser),
+                Input {
+                    key: Key::Enter, ..
+                },
+            ) => {
+                if let Some(id) = browser.selected().map(|c| c.id) {
+                    debug!("resuming conversation {id}");
+                    self.mode = Mode::Chatting;
+                    self.widget_state.pending_response = None;
+                    self.widget_state.continuing = false;
+                    self.widget_state.error = None;
+                    self.backend_tx
+                        .send(Event::ResumeConversation(id))
+                        .map_err(|e| {
+                            anyhow::anyhow!(
+                                "failed to send ResumeConversation event to backend: {e}"
+                            )
+                        })?;
+                }
+            }
+            (
+                Mode::Browsing(browser),
+                Input {
+                    key: Key::Char('v'),
+                    ctrl: false,
+                    alt: false,
+                },
+            ) => {
+                if let Some(id) = browser.selected().map(|c| c.id) {
+                    self.view_conversation(id, None)?;
+                }
+            }
+            (Mode::Searching(search), Input { key: Key::Up, .. }) => search.select_previous(),
+            (Mode::Searching(search), Input { key: Key::Down, .. }) => search.select_next(),
+            (
+                Mode::Searching(search),
+                Input {
+                    key: Key::Enter, ..
+                },
+            ) => {
+                if search.needs_search() {
+                    let query = search.query();
+                    debug!("searching for {query:?}");
+                    self.backend_tx.send(Event::Search(query)).map_err(|e| {
+                        anyhow::anyhow!("failed to send Search event to backend: {e}")
+                    })?;
+                } else if let Some((id, message_id)) = search
+                    .selected()
+                    .map(|hit| (hit.conversation_id, hit.message_id))
+                {
+                    self.view_conversation(id, Some(message_id))?;
+                }
+            }
+            (Mode::Searching(search), input) => search.input(input),
+            (
+                Mode::Settings(settings),
+                Input {
+                    key: Key::Enter, ..
+                },
+            ) => {
+                if let Some(sampling) = settings.save() {
+                    debug!(?sampling, "saving the settings");
+                    self.mode = Mode::Chatting;
+                    self.sampling = sampling.clone();
+                    self.widget_state.notice =
+                        Some("Saved the settings for the bot's next response".to_owned());
+                    self.backend_tx
+                        .send(Event::ChangeSampling(sampling))
+                        .map_err(|e| {
+                            anyhow::anyhow!("failed to send ChangeSampling event to backend: {e}")
+                        })?;
+                }
+            }
+            (Mode::Settings(settings), input) => settings.input(input),
+            (
+                Mode::Viewing {
+                    previous: Some(_), ..
+                },
+                Input { key: Key::Esc, .. },
+            ) => {
+                if let Mode::Viewing {
+                    previous: Some(previous),
+                    ..
+                } = std::mem::replace(&mut self.mode, Mode::Chatting)
+                {
+                    self.mode = *previous;
+                }
+            }
+            (
+                Mode::Viewing {
+                    conversation_id, ..
+                },
+                Input {
+                    key: Key::Char('e'),
+                    ctrl: false,
+                    alt: true,
+                },
+            ) => {
+                let id = *conversation_id;
+                self.export_conversation(Some(id))?;
+            }
+            (Mode::Viewing { previous: None, .. }, Input { key: Key::Esc, .. }) => {
+                self.app_tx
+                    .send(Event::Quit)
+                    .map_err(|e| anyhow::anyhow!("failed to send Quit event to app: {}", e))?;
+            }
+            (_, input) => {
+                trace!("ignoring {input:?} outside of the chat");
+            }
+        }
+
+        Ok(())
+    }
+
+    // Puts the selected message in the textarea to be edited, if it's the user's
+    fn start_editing(&mut self, selected: usize, is_blank: bool) {
+        let Some(message) = self.widget_state.conversation.get(selected) else {
+            return;
+        };
+        if message.sender != self.env.your_name() {
+            self.widget_state.notice = Some("Only your own messages can be edited".to_owned());
+            return;
+        }
+        if !is_blank {
+            self.widget_state.notice =
+                Some("Send or clear what you've typed before editing a message".to_owned());
+            return;
+        }
+
+        debug!("editing message {}", message.id);
+        self.widget_state.editing = Some(message.id);
+        self.widget_state.selected = None;
+        self.widget_state.textarea =
+            TextArea::new(message.content.lines().map(ToOwned::to_owned).collect());
+        self.widget_state.textarea.move_cursor(CursorMove::Bottom);
+        self.widget_state.textarea.move_cursor(CursorMove::End);
+    }
+
+    // Exports the current conversation when `id` is `None`
+    fn export_conversation(&self, id: Option<i64>) -> Result<(), anyhow::Error> {
+        debug!("exporting conversation {id:?}");
+        self.backend_tx
+            .send(Event::ExportConversation(id))
+            .map_err(|e| anyhow::anyhow!("failed to send ExportConversation event to backend: {e}"))
+    }
+
+    // Shows the branch `message_id` is on, or the newest one when it's `None`
+    fn view_conversation(&self, id: i64, message_id: Option<u64>) -> Result<(), anyhow::Error> {
+        debug!("viewing conversation {id}");
+        self.backend_tx
+            .send(Event::ViewConversation(id, message_id))
+            .map_err(|e| anyhow::anyhow!("failed to send ViewConversation event to backend: {e}"))
+    }
+
     pub async fn quit(self) -> Result<(), anyhow::Error> {
         Self::teardown_terminal(self.terminal).context("frontend quitting")
     }
@@ -246,7 +952,8 @@ impl FrontendState {
         execute!(
             terminal.backend_mut(),
             LeaveAlternateScreen,
-            DisableMouseCapture
+            DisableMouseCapture,
+            DisableBracketedPaste
         )?;
         terminal.show_cursor()?;
 
@@ -254,6 +961,107 @@ impl FrontendState {
     }
 }
 
+// `TextArea::insert_str` expects a single line, so newlines have to be inserted separately
+fn paste_into(textarea: &mut TextArea<'_>, text: &str) {
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            textarea.insert_newline();
+        }
+        textarea.insert_str(line);
+    }
+}
+
+// `focused_message` is highlighted
+fn build_conversation_entries<'a>(
+    conversation: &'a [Message],
+    focused_message: Option<u64>,
+    markdown: &mut MarkdownRenderer,
+) -> Vec<Spans<'a>> {
+    conversation
+        .iter()
+        .flat_map(|m| {
+            std::iter::once(message_header(m, focused_message))
+                .chain(markdown.render(&m.content))
+                // empty `Spans` to add a newline
+                .chain(std::iter::once(Spans::default()))
+        })
+        .collect()
+}
+
+// The sender, when the message was sent, and anything else worth knowing about it. The last span
+// is the note that the message was cut off, if it was.
+fn message_header(m: &Message, focused_message: Option<u64>) -> Spans<'_> {
+    let sender_style = if Some(m.id) == focused_message {
+        Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+    } else {
+        Style::default().add_modifier(Modifier::BOLD)
+    };
+
+    Spans::from(vec![
+        Span::styled(&m.sender, sender_style),
+        Span::raw(": "),
+        Span::styled(
+            m.timestamp.to_rfc2822(),
+            Style::default()
+                .fg(Color::Gray)
+                .add_modifier(Modifier::ITALIC),
+        ),
+        Span::styled(
+            m.usage
+                .map(|usage| format!(" ({} tokens)", usage.total_tokens()))
+                .unwrap_or_default(),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::styled(
+            m.siblings
+                .map(|siblings| {
+                    format!(" · branch {} of {}", siblings.position + 1, siblings.count)
+                })
+                .unwrap_or_default(),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::styled(
+            m.alternates
+                .as_ref()
+                .map(|alternates| {
+                    format!(
+                        " · response {} of {}",
+                        alternates.chosen + 1,
+                        alternates.responses.len()
+                    )
+                })
+                .unwrap_or_default(),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::styled(
+            if m.truncated {
+                " · cut off (Alt+C to continue)"
+            } else {
+                ""
+            },
+            Style::default().fg(Color::Yellow),
+        ),
+    ])
+}
+
+// How far down the conversation the message with the given ID starts, once wrapped to `width`
+fn lines_before_message(
+    conversation: &[Message],
+    id: u64,
+    width: u16,
+    markdown: &mut MarkdownRenderer,
+) -> u16 {
+    let before = conversation
+        .iter()
+        .position(|m| m.id == id)
+        .unwrap_or(conversation.len());
+
+    scroll::wrapped_height(
+        &build_conversation_entries(&conversation[..before], None, markdown),
+        width,
+    )
+}
+
 fn build_layout_chunks<B: Backend>(f: &mut Frame<B>) -> Vec<Rect> {
     Layout::default()
         .direction(Direction::Vertical)
@@ -278,3 +1086,37 @@ fn build_status_widget(status_message: Cow<'_, str>) -> impl Widget + '_ {
         .alignment(Alignment::Right)
         .wrap(Wrap { trim: false })
 }
+
+// The tokens used by the conversation so far, and roughly what they cost
+fn build_usage_widget(conversation: &[Message], model: &str) -> impl Widget {
+    let usage: TokenUsage = conversation.iter().filter_map(|m| m.usage).sum();
+    let text = if usage == TokenUsage::default() {
+        String::new()
+    } else {
+        match pricing::estimate_cost(model, usage) {
+            Some(cost) => format!("{} tokens used (~${cost:.2})", usage.total_tokens()),
+            None => format!("{} tokens used", usage.total_tokens()),
+        }
+    };
+
+    Paragraph::new(Spans::from(Span::raw(text)))
+        .block(Block::default().borders(Borders::NONE))
+        .style(Style::default().fg(Color::Gray))
+        .alignment(Alignment::Left)
+        .wrap(Wrap { trim: false })
+}
+
+fn build_error_widget(error: &str) -> impl Widget + '_ {
+    let text = vec![Spans::from(vec![
+        Span::styled(error, Style::default().fg(Color::Red)),
+        Span::styled(
+            " (press Ctrl+T to try again)",
+            Style::default().fg(Color::Gray),
+        ),
+    ])];
+
+    Paragraph::new(text)
+        .block(Block::default().borders(Borders::NONE))
+        .alignment(Alignment::Right)
+        .wrap(Wrap { trim: false })
+}
@@ -0,0 +1,111 @@
+use crate::message::ConversationSummary;
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+// Prompts and messages can be long, so only the start of them is shown
+const PREVIEW_LENGTH: usize = 80;
+
+/// A list of every saved conversation, for picking one to view or resume.
+#[derive(Default)]
+pub(super) struct ConversationBrowser {
+    // `None` until the backend sends the list
+    conversations: Option<Vec<ConversationSummary>>,
+    state: ListState,
+}
+
+impl ConversationBrowser {
+    pub fn set_conversations(&mut self, conversations: Vec<ConversationSummary>) {
+        self.state.select((!conversations.is_empty()).then_some(0));
+        self.conversations = Some(conversations);
+    }
+
+    pub fn selected(&self) -> Option<&ConversationSummary> {
+        self.conversations
+            .as_ref()
+            .zip(self.state.selected())
+            .and_then(|(conversations, i)| conversations.get(i))
+    }
+
+    pub fn select_previous(&mut self) {
+        if let Some(i) = self.state.selected() {
+            self.state.select(Some(i.saturating_sub(1)));
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        let len = self.conversations.as_ref().map_or(0, Vec::len);
+        if let Some(i) = self.state.selected() {
+            self.state.select(Some((i + 1).min(len.saturating_sub(1))));
+        }
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let block = Block::default()
+            .title(" Conversations ")
+            .borders(Borders::ALL);
+
+        let conversations = match &self.conversations {
+            Some(conversations) if !conversations.is_empty() => conversations,
+            Some(_) => {
+                let p = Paragraph::new("There aren't any saved conversations yet.").block(block);
+                f.render_widget(p, area);
+                return;
+            }
+            None => {
+                let p = Paragraph::new("Loading conversations...").block(block);
+                f.render_widget(p, area);
+                return;
+            }
+        };
+
+        let items: Vec<_> = conversations
+            .iter()
+            .map(|c| {
+                let header = Spans::from(vec![
+                    Span::styled(
+                        c.created_at.format("%Y-%m-%d %H:%M").to_string(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(
+                        " · {} message{} · ",
+                        c.message_count,
+                        if c.message_count == 1 { "" } else { "s" }
+                    )),
+                    Span::styled(
+                        preview(&c.prompt),
+                        Style::default().add_modifier(Modifier::ITALIC),
+                    ),
+                ]);
+                let first_message = Spans::from(Span::styled(
+                    format!("  {}", preview(c.first_message.as_deref().unwrap_or(""))),
+                    Style::default().fg(Color::Gray),
+                ));
+
+                ListItem::new(vec![header, first_message])
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, area, &mut self.state);
+    }
+}
+
+fn preview(text: &str) -> String {
+    // Everything is shown on one line, so newlines would mess up the list
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > PREVIEW_LENGTH {
+        let mut preview: String = text.chars().take(PREVIEW_LENGTH).collect();
+        preview.push('…');
+        preview
+    } else {
+        text
+    }
+}
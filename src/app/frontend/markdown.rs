@@ -0,0 +1,465 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use std::collections::HashMap;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+use tracing::debug;
+use tui::{
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+};
+
+// Everything ever rendered is cached, which is fine for a few conversations but not forever
+const CACHE_CAPACITY: usize = 1000;
+const THEME: &str = "base16-ocean.dark";
+// Lists and block quotes nested deeper than this would be mostly indentation
+const MAX_NESTING: usize = 8;
+
+/// Turns Markdown into styled lines for the conversation.
+pub(super) struct MarkdownRenderer {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    // Syntax highlighting is too slow to redo for every message every time the screen is drawn
+    cache: HashMap<String, Vec<Spans<'static>>>,
+}
+
+impl Default for MarkdownRenderer {
+    fn default() -> Self {
+        let mut themes = ThemeSet::load_defaults();
+
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes
+                .themes
+                .remove(THEME)
+                .expect("the default themes include base16-ocean.dark"),
+            cache: HashMap::new(),
+        }
+    }
+}
+
+impl MarkdownRenderer {
+    /// Render a message that won't change, reusing the last rendering of it if there is one.
+    pub fn render(&mut self, text: &str) -> Vec<Spans<'static>> {
+        if let Some(lines) = self.cache.get(text) {
+            return lines.clone();
+        }
+
+        if self.cache.len() >= CACHE_CAPACITY {
+            self.cache.clear();
+        }
+        let lines = self.render_uncached(text);
+        self.cache.insert(text.to_owned(), lines.clone());
+
+        lines
+    }
+
+    /// Render a message that's still being written, which wouldn't be worth caching.
+    ///
+    /// Messages are shown as plain text instead if their code can't be highlighted, or if their
+    /// lists and block quotes are nested too deeply to lay out. Code in a language that isn't known
+    /// is still boxed, just without highlighting.
+    pub fn render_uncached(&self, text: &str) -> Vec<Spans<'static>> {
+        let mut writer = Writer::new(self);
+        match writer.write(text) {
+            Ok(()) => writer.finish(),
+            Err(e) => {
+                debug!("failed to render Markdown, showing it as plain text instead: {e}");
+                plain_text(text)
+            }
+        }
+    }
+
+    fn highlight(&self, code: &str, language: &str) -> Result<Vec<Spans<'static>>, syntect::Error> {
+        let syntax = self
+            .syntaxes
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+
+        LinesWithEndings::from(code)
+            .map(|line| {
+                let spans = highlighter
+                    .highlight_line(line, &self.syntaxes)?
+                    .into_iter()
+                    .map(|(style, text)| {
+                        let color = style.foreground;
+                        Span::styled(
+                            text.trim_end_matches('\n').to_owned(),
+                            Style::default().fg(Color::Rgb(color.r, color.g, color.b)),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(Spans::from(spans))
+            })
+            .collect()
+    }
+}
+
+fn plain_text(text: &str) -> Vec<Spans<'static>> {
+    text.lines()
+        .map(|line| Spans::from(Span::raw(line.to_owned())))
+        .collect()
+}
+
+// Keeps track of where in the document the parser is, and builds up the lines rendered so far
+struct Writer<'r> {
+    renderer: &'r MarkdownRenderer,
+    lines: Vec<Spans<'static>>,
+    line: Vec<Span<'static>>,
+    // Nested styles, like a bold word in an italic sentence
+    styles: Vec<Style>,
+    // What goes at the start of each line, for lists and block quotes
+    indents: Vec<Span<'static>>,
+    // The bullet or number of a list item that hasn't had its first line yet
+    bullet: Option<Span<'static>>,
+    // The next number of each ordered list being written, or `None` for bulleted lists
+    lists: Vec<Option<u64>>,
+    // The language and contents of a code block that's being written
+    code_block: Option<(String, String)>,
+    link: Option<String>,
+    // Blocks are separated by a blank line
+    needs_blank_line: bool,
+}
+
+impl<'r> Writer<'r> {
+    fn new(renderer: &'r MarkdownRenderer) -> Self {
+        Self {
+            renderer,
+            lines: Vec::new(),
+            line: Vec::new(),
+            styles: vec![Style::default()],
+            indents: Vec::new(),
+            bullet: None,
+            lists: Vec::new(),
+            code_block: None,
+            link: None,
+            needs_blank_line: false,
+        }
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), anyhow::Error> {
+        let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+        for event in Parser::new_ext(text, options) {
+            match event {
+                Event::Start(tag) => self.start(tag)?,
+                Event::End(tag) => self.end(tag)?,
+                Event::Text(text) => match &mut self.code_block {
+                    Some((_, code)) => code.push_str(&text),
+                    None => self.push_text(&text, self.style()),
+                },
+                Event::Code(code) => {
+                    self.push_text(&code, self.style().fg(Color::Yellow));
+                }
+                Event::Html(html) => self.push_text(&html, self.style()),
+                Event::FootnoteReference(label) => {
+                    self.push_text(&format!("[^{label}]"), self.style());
+                }
+                // Messages are written more like chat than documents, so their line breaks are
+                // kept
+                Event::SoftBreak | Event::HardBreak => self.end_line(),
+                Event::Rule => {
+                    self.start_block();
+                    self.push_text(&"─".repeat(20), Style::default().fg(Color::DarkGray));
+                    self.end_block();
+                }
+                Event::TaskListMarker(done) => {
+                    self.push_text(if done { "[x] " } else { "[ ] " }, self.style());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn start(&mut self, tag: Tag<'_>) -> Result<(), anyhow::Error> {
+        match tag {
+            Tag::Paragraph => self.start_block(),
+            Tag::Heading(level, ..) => {
+                self.start_block();
+                let style = match level {
+                    HeadingLevel::H1 | HeadingLevel::H2 => Style::default()
+                        .fg(Color::Cyan)
+                        .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                    _ => Style::default()
+                        .fg(Color::Cyan)
+                        .add_modifier(Modifier::BOLD),
+                };
+                self.styles.push(style);
+            }
+            Tag::BlockQuote => {
+                self.start_block();
+                self.indents
+                    .push(Span::styled("│ ", Style::default().fg(Color::DarkGray)));
+                self.styles
+                    .push(self.style().add_modifier(Modifier::ITALIC));
+            }
+            Tag::CodeBlock(kind) => {
+                self.start_block();
+                let language = match kind {
+                    // Fences can have more than the language after them, like "rust,ignore"
+                    CodeBlockKind::Fenced(info) => info
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .next()
+                        .unwrap_or_default()
+                        .to_owned(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code_block = Some((language, String::new()));
+            }
+            Tag::List(start) => {
+                // Nested lists go right under their item, without a blank line
+                if self.lists.is_empty() {
+                    self.start_block();
+                } else {
+                    self.end_line();
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.end_line();
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_owned(),
+                };
+                self.indents
+                    .push(Span::raw(" ".repeat(bullet.chars().count())));
+                self.bullet = Some(Span::styled(bullet, Style::default().fg(Color::Gray)));
+            }
+            Tag::Emphasis => self
+                .styles
+                .push(self.style().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.styles.push(self.style().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self
+                .styles
+                .push(self.style().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link(_, url, _) | Tag::Image(_, url, _) => {
+                self.link = Some(url.to_string());
+                self.styles
+                    .push(self.style().add_modifier(Modifier::UNDERLINED));
+            }
+            // Tables aren't turned on, so there's nothing else that can turn up
+            _ => {}
+        }
+
+        if self.indents.len() > MAX_NESTING {
+            anyhow::bail!("lists and block quotes are nested more than {MAX_NESTING} deep");
+        }
+
+        Ok(())
+    }
+
+    fn end(&mut self, tag: Tag<'_>) -> Result<(), anyhow::Error> {
+        match tag {
+            Tag::Paragraph => self.end_block(),
+            Tag::Heading(..) => {
+                self.styles.pop();
+                self.end_block();
+            }
+            Tag::BlockQuote => {
+                self.styles.pop();
+                self.end_line();
+                self.indents.pop();
+                self.needs_blank_line = true;
+            }
+            Tag::CodeBlock(_) => {
+                if let Some((language, code)) = self.code_block.take() {
+                    self.write_code_block(&language, &code)?;
+                }
+                self.end_block();
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.end_block();
+                }
+            }
+            Tag::Item => {
+                self.end_line();
+                self.indents.pop();
+                self.bullet = None;
+                // Items of loose lists are paragraphs, but they still go right after each other
+                self.needs_blank_line = false;
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough => {
+                self.styles.pop();
+            }
+            Tag::Link(..) | Tag::Image(..) => {
+                self.styles.pop();
+                if let Some(url) = self.link.take() {
+                    self.push_text(&format!(" ({url})"), Style::default().fg(Color::DarkGray));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn style(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+
+    fn start_block(&mut self) {
+        self.end_line();
+        if self.needs_blank_line {
+            self.lines.push(Spans::default());
+            self.needs_blank_line = false;
+        }
+    }
+
+    fn end_block(&mut self) {
+        self.end_line();
+        self.needs_blank_line = true;
+    }
+
+    fn push_text(&mut self, text: &str, style: Style) {
+        if self.line.is_empty() {
+            self.start_line();
+        }
+        self.line.push(Span::styled(text.to_owned(), style));
+    }
+
+    fn start_line(&mut self) {
+        self.line.extend(self.indents.iter().cloned());
+        // The first line of a list item has its bullet where the indent would be
+        if let Some(bullet) = self.bullet.take() {
+            self.line.pop();
+            self.line.push(bullet);
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            self.lines.push(Spans::from(std::mem::take(&mut self.line)));
+        }
+    }
+
+    // Code goes in a box, with the language in its top edge
+    fn write_code_block(&mut self, language: &str, code: &str) -> Result<(), anyhow::Error> {
+        let code_lines = self.renderer.highlight(code, language)?;
+        let title = if language.is_empty() {
+            String::new()
+        } else {
+            format!(" {language} ")
+        };
+        // The box is wide enough for the title too
+        let width = code_lines
+            .iter()
+            .map(Spans::width)
+            .chain([title.chars().count()])
+            .max()
+            .unwrap_or(0);
+        let border = Style::default().fg(Color::DarkGray);
+
+        let top = format!(
+            "╭─{title}{}╮",
+            "─".repeat((width + 1).saturating_sub(title.chars().count()))
+        );
+        self.push_text(&top, border);
+        self.end_line();
+
+        for line in code_lines {
+            let padding = " ".repeat(width - line.width());
+            self.push_text("│ ", border);
+            self.line.extend(line.0);
+            self.push_text(&padding, Style::default());
+            self.push_text(" │", border);
+            self.end_line();
+        }
+
+        self.push_text(&format!("╰{}╯", "─".repeat(width + 2)), border);
+        self.end_line();
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<Spans<'static>> {
+        self.end_line();
+        self.lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn render_to_strings(text: &str) -> Vec<String> {
+        MarkdownRenderer::default()
+            .render_uncached(text)
+            .into_iter()
+            .map(|spans| spans.0.into_iter().map(|span| span.content).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_lists_are_indented() {
+        let text = "Shopping:\n\n- eggs\n- flour\n  1. plain\n  2. self-raising\n\nThat's all.";
+
+        assert_eq!(
+            render_to_strings(text),
+            vec![
+                "Shopping:",
+                "",
+                "• eggs",
+                "• flour",
+                "  1. plain",
+                "  2. self-raising",
+                "",
+                "That's all.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_code_in_an_unknown_language_is_boxed_without_highlighting() {
+        assert_eq!(
+            render_to_strings("```nonsense\nx = 1\n```"),
+            vec!["╭─ nonsense ─╮", "│ x = 1      │", "╰────────────╯"]
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_markdown_is_shown_as_plain_text() {
+        let text = format!("{} Too deep.\n\nReally.", ">".repeat(MAX_NESTING + 1));
+
+        assert_eq!(
+            render_to_strings(&text),
+            vec![
+                format!("{} Too deep.", ">".repeat(MAX_NESTING + 1)),
+                String::new(),
+                "Really.".to_owned()
+            ]
+        );
+        // Up to the limit is fine
+        let text = format!("{} Deep enough.", ">".repeat(MAX_NESTING));
+        assert_eq!(
+            render_to_strings(&text),
+            vec![format!("{}Deep enough.", "│ ".repeat(MAX_NESTING))]
+        );
+    }
+
+    #[test]
+    fn test_code_blocks_are_boxed() {
+        let text = "Try this:\n\n```rust\nfn main() {}\n```";
+
+        assert_eq!(
+            render_to_strings(text),
+            vec![
+                "Try this:",
+                "",
+                "╭─ rust ───────╮",
+                "│ fn main() {} │",
+                "╰──────────────╯",
+            ]
+        );
+    }
+}
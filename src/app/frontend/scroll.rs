@@ -0,0 +1,236 @@
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+use tui_textarea::{Input, Key};
+
+// Scrolling a line at a time with a mouse wheel is painfully slow
+const MOUSE_SCROLL_LINES: u16 = 3;
+
+/// Where the conversation is scrolled to. Until the user scrolls up, the end of the conversation
+/// is followed as it grows.
+#[derive(Debug, Default)]
+pub(super) struct ConversationScroll {
+    // The first line shown, or `None` to follow the end of the conversation
+    top: Option<u16>,
+    // These are as of the last time the conversation was drawn
+    max_top: u16,
+    page_height: u16,
+}
+
+impl ConversationScroll {
+    /// Start out showing `line` at the top, as long as there's enough below it to fill the screen.
+    pub fn at_line(line: u16) -> Self {
+        Self {
+            top: Some(line),
+            ..Self::default()
+        }
+    }
+
+    fn offset(&self) -> u16 {
+        self.top.unwrap_or(self.max_top).min(self.max_top)
+    }
+
+    pub fn scroll_up(&mut self, lines: u16) {
+        self.top = Some(self.offset().saturating_sub(lines));
+    }
+
+    pub fn scroll_down(&mut self, lines: u16) {
+        let top = self.offset().saturating_add(lines);
+        // Scrolling all the way down goes back to following the conversation
+        self.top = (top < self.max_top).then_some(top);
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.top = None;
+    }
+
+    /// Scroll so that `line` is at the top, unless it's already on screen.
+    pub fn scroll_into_view(&mut self, line: u16) {
+        let offset = self.offset();
+        if line < offset || line >= offset.saturating_add(self.page_height) {
+            self.top = (line < self.max_top).then_some(line);
+        }
+    }
+
+    /// Scroll if `input` is one of the scrolling keys, returning whether it was. While `composing`
+    /// a message, the keys that also move around the message only scroll with Ctrl held.
+    pub fn handle_input(&mut self, input: &Input, composing: bool) -> bool {
+        // Leave a line of overlap so it's easier to keep track of where you were
+        let page = self.page_height.saturating_sub(1).max(1);
+        match input.key {
+            Key::MouseScrollUp => self.scroll_up(MOUSE_SCROLL_LINES),
+            Key::MouseScrollDown => self.scroll_down(MOUSE_SCROLL_LINES),
+            _ if composing && !input.ctrl => return false,
+            Key::PageUp => self.scroll_up(page),
+            Key::PageDown => self.scroll_down(page),
+            Key::Home => self.top = Some(0),
+            Key::End => self.scroll_to_bottom(),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Draw the conversation scrolled to wherever it's scrolled to, with a scrollbar along the
+    /// right when it doesn't all fit.
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect, entries: Vec<Spans<'_>>) {
+        let block = Block::default().borders(Borders::BOTTOM);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let text_area = Rect {
+            width: inner.width.saturating_sub(1),
+            ..inner
+        };
+        let scrollbar_area = Rect {
+            x: text_area.right(),
+            width: inner.width - text_area.width,
+            ..inner
+        };
+
+        let height = wrapped_height(&entries, text_area.width);
+        self.page_height = text_area.height;
+        self.max_top = height.saturating_sub(text_area.height);
+        let offset = self.offset();
+
+        let conversation = Paragraph::new(entries)
+            .scroll((offset, 0))
+            .wrap(Wrap { trim: false });
+        f.render_widget(conversation, text_area);
+
+        // A terminal too short to show the conversation has no room for a scrollbar either
+        if self.max_top > 0 && scrollbar_area.height > 0 {
+            f.render_widget(
+                build_scrollbar(offset, self.max_top, scrollbar_area.height),
+                scrollbar_area,
+            );
+        }
+    }
+}
+
+fn build_scrollbar(offset: u16, max_top: u16, height: u16) -> Paragraph<'static> {
+    let height = u32::from(height);
+    let content_height = u32::from(max_top) + height;
+    let thumb_height = (height * height / content_height).max(1);
+    let thumb_top = u32::from(offset) * height.saturating_sub(thumb_height) / u32::from(max_top);
+
+    let lines: Vec<_> = (0..height)
+        .map(|line| {
+            if (thumb_top..thumb_top + thumb_height).contains(&line) {
+                Spans::from(Span::styled("█", Style::default().fg(Color::Gray)))
+            } else {
+                Spans::from(Span::styled("│", Style::default().fg(Color::DarkGray)))
+            }
+        })
+        .collect();
+
+    Paragraph::new(lines)
+}
+
+/// How many lines `entries` take up once they're wrapped to `width`. This wraps at spaces like
+/// the conversation's `Paragraph` does, so it comes out the same in all but the weirdest cases.
+pub(super) fn wrapped_height(entries: &[Spans<'_>], width: u16) -> u16 {
+    let width = usize::from(width.max(1));
+    let lines: usize = entries
+        .iter()
+        .map(|spans| {
+            let text: String = spans.0.iter().map(|span| span.content.as_ref()).collect();
+            wrapped_line_count(&text, width)
+        })
+        .sum();
+
+    lines.try_into().unwrap_or(u16::MAX)
+}
+
+fn wrapped_line_count(text: &str, width: usize) -> usize {
+    let mut lines = 1;
+    let mut line_length = 0;
+    for word in text.split(' ') {
+        let word_length = word.chars().count();
+        if line_length > 0 && line_length + 1 + word_length > width {
+            lines += 1;
+            line_length = 0;
+        } else if line_length > 0 {
+            line_length += 1;
+        }
+
+        // Words too long for a line of their own get split up
+        line_length += word_length;
+        while line_length > width {
+            lines += 1;
+            line_length -= width;
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_scrolling_back_to_the_bottom_follows_the_conversation() {
+        let mut scroll = ConversationScroll {
+            top: None,
+            max_top: 50,
+            page_height: 10,
+        };
+        assert_eq!(scroll.offset(), 50);
+
+        scroll.scroll_up(20);
+        assert_eq!(scroll.offset(), 30);
+        // New messages don't move the view while scrolled up
+        scroll.max_top = 60;
+        assert_eq!(scroll.offset(), 30);
+
+        scroll.scroll_down(100);
+        assert_eq!(scroll.top, None);
+        scroll.max_top = 70;
+        assert_eq!(scroll.offset(), 70);
+    }
+
+    #[test]
+    fn test_home_and_end_are_left_to_the_message_being_written() {
+        let mut scroll = ConversationScroll {
+            top: None,
+            max_top: 50,
+            page_height: 10,
+        };
+        let home = Input {
+            key: Key::Home,
+            ctrl: false,
+            alt: false,
+        };
+        assert!(!scroll.handle_input(&home, true));
+        assert_eq!(scroll.offset(), 50);
+
+        assert!(scroll.handle_input(&Input { ctrl: true, ..home }, true));
+        assert_eq!(scroll.offset(), 0);
+        assert!(scroll.handle_input(&home, false));
+    }
+
+    #[test]
+    fn test_wrapped_height_breaks_lines_at_spaces() {
+        let entries = vec![
+            Spans::from("the quick brown fox"),
+            Spans::default(),
+            Spans::from("abcdefghijklmnopqrstuvwxy"),
+        ];
+
+        // "the quick" / "brown fox", an empty line, and then a word that needs 3 lines
+        assert_eq!(wrapped_height(&entries, 10), 6);
+    }
+
+    #[test]
+    fn test_scrollbar_with_no_room_to_draw_it() {
+        // The thumb is never less than a line tall, even when there aren't any lines
+        build_scrollbar(5, 10, 0);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::message::SearchHit;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+use tui_textarea::{Input, TextArea};
+
+/// A search box, and the messages that matched the last search.
+pub(super) struct SearchOverlay {
+    textarea: TextArea<'static>,
+    // The query the current hits are for, or `None` if nothing has been searched for yet
+    searched_query: Option<String>,
+    hits: Vec<SearchHit>,
+    state: ListState,
+}
+
+impl Default for SearchOverlay {
+    fn default() -> Self {
+        let mut textarea = TextArea::default();
+        textarea.set_block(Block::default().title(" Search ").borders(Borders::ALL));
+
+        Self {
+            textarea,
+            searched_query: None,
+            hits: Vec::new(),
+            state: ListState::default(),
+        }
+    }
+}
+
+impl SearchOverlay {
+    pub fn input(&mut self, input: Input) {
+        self.textarea.input(input);
+    }
+
+    /// The search box is one line, so pasted lines are joined with spaces.
+    pub fn paste(&mut self, text: &str) {
+        self.textarea
+            .insert_str(text.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+
+    pub fn query(&self) -> String {
+        self.textarea.lines().join(" ").trim().to_owned()
+    }
+
+    /// Whether the query has changed since the last search.
+    pub fn needs_search(&self) -> bool {
+        self.searched_query.as_deref() != Some(self.query().as_str())
+    }
+
+    pub fn set_hits(&mut self, hits: Vec<SearchHit>) {
+        self.state.select((!hits.is_empty()).then_some(0));
+        self.searched_query = Some(self.query());
+        self.hits = hits;
+    }
+
+    pub fn selected(&self) -> Option<&SearchHit> {
+        self.state.selected().and_then(|i| self.hits.get(i))
+    }
+
+    pub fn select_previous(&mut self) {
+        if let Some(i) = self.state.selected() {
+            self.state.select(Some(i.saturating_sub(1)));
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if let Some(i) = self.state.selected() {
+            self.state
+                .select(Some((i + 1).min(self.hits.len().saturating_sub(1))));
+        }
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(area);
+
+        f.render_widget(self.textarea.widget(), chunks[0]);
+
+        let block = Block::default().borders(Borders::ALL);
+        if self.hits.is_empty() {
+            let text = match &self.searched_query {
+                Some(query) => format!("No messages matched \"{query}\"."),
+                None => "Type some words and press Enter to search every conversation.".to_owned(),
+            };
+            f.render_widget(Paragraph::new(text).block(block), chunks[1]);
+            return;
+        }
+
+        let items: Vec<_> = self
+            .hits
+            .iter()
+            .map(|hit| {
+                let header = Spans::from(vec![
+                    Span::styled(
+                        hit.timestamp.format("%Y-%m-%d %H:%M").to_string(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(
+                        " · conversation {} · {}",
+                        hit.conversation_id, hit.sender
+                    )),
+                ]);
+
+                ListItem::new(vec![header, highlight_snippet(&hit.snippet)])
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, chunks[1], &mut self.state);
+    }
+}
+
+// The database marks the matching words in a snippet with brackets
+fn highlight_snippet(snippet: &str) -> Spans<'static> {
+    let snippet = snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut spans = vec![Span::raw("  ")];
+    let mut rest = snippet.as_str();
+    while let Some((before, after)) = rest.split_once(SearchHit::MATCH_START) {
+        let Some((matched, after)) = after.split_once(SearchHit::MATCH_END) else {
+            break;
+        };
+        spans.push(Span::styled(
+            before.to_owned(),
+            Style::default().fg(Color::Gray),
+        ));
+        spans.push(Span::styled(
+            matched.to_owned(),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ));
+        rest = after;
+    }
+    spans.push(Span::styled(
+        rest.to_owned(),
+        Style::default().fg(Color::Gray),
+    ));
+
+    Spans::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_brackets_in_snippets_are_left_alone() {
+        let spans = highlight_snippet("let v = [1, 2];\n\u{2}vec\u{3}![3]");
+        let text: Vec<_> = spans.0.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, vec!["  ", "let v = [1, 2]; ", "vec", "![3]"]);
+        assert_eq!(spans.0[2].style.fg, Some(Color::Yellow));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::sampling::{self, SamplingParameters};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Span,
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+
+// Each setting's name, and what it can be set to
+const FIELDS: [(&str, &str); 6] = [
+    ("Temperature", "0 to 2"),
+    ("Top P", "0 to 1"),
+    ("Presence penalty", "-2 to 2"),
+    ("Frequency penalty", "-2 to 2"),
+    ("Stop sequences", "comma-separated, \\n for newlines"),
+    ("Seed", "a whole number"),
+];
+
+/// A text box for each of the sampling parameters, for changing them mid-conversation.
+pub(super) struct SettingsPanel {
+    fields: Vec<TextArea<'static>>,
+    focused: usize,
+    // Why the settings couldn't be saved, the last time that was tried
+    error: Option<String>,
+}
+
+impl SettingsPanel {
+    pub fn new(sampling: &SamplingParameters) -> Self {
+        let values = [
+            sampling.temperature.map(|value| value.to_string()),
+            sampling.top_p.map(|value| value.to_string()),
+            sampling.presence_penalty.map(|value| value.to_string()),
+            sampling.frequency_penalty.map(|value| value.to_string()),
+            Some(sampling::format_stop_sequences(&sampling.stop)),
+            sampling.seed.map(|value| value.to_string()),
+        ];
+        let fields = values
+            .into_iter()
+            .zip(FIELDS)
+            .map(|(value, (name, range))| {
+                let mut textarea = TextArea::new(vec![value.unwrap_or_default()]);
+                textarea.set_block(
+                    Block::default()
+                        .title(format!(" {name} ({range}) "))
+                        .borders(Borders::ALL),
+                );
+                textarea.set_cursor_line_style(Style::default());
+                textarea.move_cursor(CursorMove::End);
+                textarea
+            })
+            .collect();
+
+        let mut panel = Self {
+            fields,
+            focused: 0,
+            error: None,
+        };
+        panel.focus(0);
+        panel
+    }
+
+    pub fn input(&mut self, input: Input) {
+        match input.key {
+            Key::Up => self.focus(self.focused.saturating_sub(1)),
+            Key::Down | Key::Tab => self.focus((self.focused + 1).min(FIELDS.len() - 1)),
+            _ => {
+                self.fields[self.focused].input(input);
+            }
+        }
+    }
+
+    /// Each setting is one line, so pasted newlines are written the way stop sequences write them.
+    pub fn paste(&mut self, text: &str) {
+        self.fields[self.focused].insert_str(text.trim_end_matches('\n').replace('\n', "\\n"));
+    }
+
+    /// The settings as they've been filled in, or `None` if any of them aren't valid. Blank ones
+    /// are left up to the server.
+    pub fn save(&mut self) -> Option<SamplingParameters> {
+        let sampling = self.parse().and_then(|sampling| {
+            sampling.validate()?;
+            Ok(sampling)
+        });
+        match sampling {
+            Ok(sampling) => Some(sampling),
+            Err(e) => {
+                self.error = Some(e.to_string());
+                None
+            }
+        }
+    }
+
+    fn parse(&self) -> Result<SamplingParameters, anyhow::Error> {
+        Ok(SamplingParameters {
+            temperature: self.parse_field(0)?,
+            top_p: self.parse_field(1)?,
+            presence_penalty: self.parse_field(2)?,
+            frequency_penalty: self.parse_field(3)?,
+            stop: sampling::parse_stop_sequences(&self.text(4)),
+            seed: self.parse_field(5)?,
+        })
+    }
+
+    fn parse_field<T: std::str::FromStr>(&self, i: usize) -> Result<Option<T>, anyhow::Error> {
+        let text = self.text(i);
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let (name, range) = FIELDS[i];
+        text.trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("{name} should be {range}, not \"{text}\""))
+    }
+
+    fn text(&self, i: usize) -> String {
+        self.fields[i].lines().join("")
+    }
+
+    // Only the focused field shows its cursor
+    fn focus(&mut self, i: usize) {
+        self.focused = i;
+        for (j, field) in self.fields.iter_mut().enumerate() {
+            let style = if i == j {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            field.set_cursor_style(style);
+        }
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let constraints: Vec<_> = FIELDS
+            .iter()
+            .map(|_| Constraint::Length(3))
+            .chain([Constraint::Min(0)])
+            .collect();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(area);
+
+        for (field, chunk) in self.fields.iter().zip(&chunks) {
+            f.render_widget(field.widget(), *chunk);
+        }
+
+        let note = match &self.error {
+            Some(error) => Span::styled(error.as_str(), Style::default().fg(Color::Red)),
+            None => Span::styled(
+                "Blank settings are left up to the server. Changes apply from the bot's next response.",
+                Style::default().fg(Color::Gray),
+            ),
+        };
+        f.render_widget(
+            Paragraph::new(note).wrap(Wrap { trim: false }),
+            chunks[FIELDS.len()],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_settings_start_out_as_they_were_and_mistakes_are_caught() {
+        let sampling = SamplingParameters {
+            temperature: Some(0.5),
+            stop: vec!["\nUser:".to_owned()],
+            ..Default::default()
+        };
+        let mut panel = SettingsPanel::new(&sampling);
+        assert_eq!(panel.save(), Some(sampling));
+
+        panel.focus(5);
+        panel.paste("abc");
+        assert_eq!(panel.save(), None);
+        assert_eq!(
+            panel.error.as_deref(),
+            Some("Seed should be a whole number, not \"abc\"")
+        );
+    }
+}
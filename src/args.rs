@@ -1,15 +1,25 @@
-use std::path::{PathBuf, Path};
+use std::path::{Path, PathBuf};
 
-use clap::{command, Parser};
+use clap::{Parser, Subcommand};
+
+use crate::{export::ExportFormat, provider::ProviderKind};
 
 /// A clap args struct containing the command line arguments for this program
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// When passed, resume the previous conversation instead of starting a new one.
     #[clap(long, default_value_t = false)]
     resume: bool,
 
+    /// Read through the saved conversation with this ID instead of chatting. Nothing is sent to
+    /// the bot, so this never costs anything.
+    #[clap(long, value_name = "CONVERSATION_ID", conflicts_with = "resume")]
+    view: Option<i64>,
+
     /// The OpenAI model to use.
     /// If not provided, the OPENAI_MODEL_NAME environment variable will be used.
     /// Defaults to "text-davinci-003".
@@ -40,9 +50,9 @@ pub struct Args {
     #[clap(long)]
     token_limit: Option<u32>,
 
-    /// The number of messages to use as context for the prompt.
+    /// The most messages to use as context for the prompt.
     /// If not provided, the PROMPT_CONTEXT_LENGTH environment variable will be used.
-    /// Defaults to 5.
+    /// Defaults to as many as fit in the model's context window.
     #[clap(long)]
     prompt_context_length: Option<usize>,
 
@@ -51,6 +61,113 @@ pub struct Args {
     /// Defaults to "chatbot.db".
     #[clap(long)]
     db_path: Option<PathBuf>,
+
+    /// When passed, wait for the bot's whole response instead of displaying it as it's written.
+    /// If not provided, the STREAM_RESPONSES environment variable will be used.
+    /// Defaults to streaming responses.
+    #[clap(long, default_value_t = false)]
+    no_stream: bool,
+
+    /// How many responses to ask for at once when regenerating the bot's response. They're all
+    /// kept, to choose between.
+    /// If not provided, the ALTERNATES environment variable will be used.
+    /// Defaults to 1.
+    #[clap(long)]
+    alternates: Option<u32>,
+
+    /// How random the bot's responses are, from 0 to 2.
+    /// If not provided, the TEMPERATURE environment variable will be used.
+    /// Defaults to 0.
+    #[clap(long)]
+    temperature: Option<f32>,
+
+    /// Only sample from the tokens making up this much of the probability, from 0 to 1.
+    /// If not provided, the TOP_P environment variable will be used.
+    /// Defaults to the server's default.
+    #[clap(long)]
+    top_p: Option<f32>,
+
+    /// How much to encourage the bot to talk about new things, from -2 to 2.
+    /// If not provided, the PRESENCE_PENALTY environment variable will be used.
+    /// Defaults to the server's default.
+    #[clap(long, allow_negative_numbers = true)]
+    presence_penalty: Option<f32>,
+
+    /// How much to discourage the bot from repeating itself, from -2 to 2.
+    /// If not provided, the FREQUENCY_PENALTY environment variable will be used.
+    /// Defaults to the server's default.
+    #[clap(long, allow_negative_numbers = true)]
+    frequency_penalty: Option<f32>,
+
+    /// A sequence that ends the bot's response when it would write it. Pass this up to 4 times.
+    /// Write newlines as "\n".
+    /// If not provided, the STOP_SEQUENCES environment variable will be used, as a comma-separated
+    /// list.
+    #[clap(long = "stop", value_name = "SEQUENCE")]
+    stop_sequences: Vec<String>,
+
+    /// Ask for the same response every time for the same conversation. This is only a best effort.
+    /// If not provided, the SEED environment variable will be used.
+    #[clap(long, allow_negative_numbers = true)]
+    seed: Option<i64>,
+
+    /// Where the bot's responses come from.
+    /// If not provided, the PROVIDER environment variable will be used.
+    /// Defaults to "openai".
+    #[clap(long, value_enum)]
+    provider: Option<ProviderKind>,
+
+    /// The path to a script of responses, one per line, for the scripted provider.
+    /// If not provided, the SCRIPT_FILE_PATH environment variable will be used.
+    #[clap(long)]
+    script: Option<PathBuf>,
+
+    /// The base URL of the OpenAI API, or of a local server that speaks the same protocol.
+    /// If not provided, the OPENAI_BASE_URL environment variable will be used.
+    /// Defaults to "https://api.openai.com/v1".
+    #[clap(long)]
+    base_url: Option<String>,
+}
+
+/// Things to do instead of chatting
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Search every saved conversation for messages containing all of the given words.
+    Search {
+        /// The words to search for.
+        #[clap(required = true)]
+        query: Vec<String>,
+
+        /// The most matching messages to show.
+        #[clap(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Write saved conversations out as Markdown, JSON lines, or a web page.
+    Export {
+        /// The ID of the conversation to export.
+        #[clap(required_unless_present = "all", conflicts_with = "all")]
+        conversation_id: Option<i64>,
+
+        /// Export every saved conversation, each to its own file.
+        #[clap(long, default_value_t = false)]
+        all: bool,
+
+        #[clap(long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+
+        /// Where to write the export. When exporting one conversation this is a file, and the
+        /// export is printed if it isn't given. With `--all` it's a directory, which defaults to
+        /// the current one.
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Save conversations from a ChatGPT data export's conversations.json, or from JSON logs.
+    /// Conversations that have already been imported are skipped.
+    Import {
+        /// The files to import.
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
 }
 
 impl Args {
@@ -58,10 +175,23 @@ impl Args {
         Parser::parse()
     }
 
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
+    /// Whether the bot will be talked to at all. When it won't, its settings don't matter.
+    pub fn talks_to_bot(&self) -> bool {
+        self.command.is_none() && self.view.is_none()
+    }
+
     pub fn resume(&self) -> bool {
         self.resume
     }
 
+    pub fn view(&self) -> Option<i64> {
+        self.view
+    }
+
     pub fn model(&self) -> Option<&str> {
         self.model.as_deref()
     }
@@ -89,4 +219,48 @@ impl Args {
     pub fn db_path(&self) -> Option<&Path> {
         self.db_path.as_deref()
     }
-}
\ No newline at end of file
+
+    pub fn no_stream(&self) -> bool {
+        self.no_stream
+    }
+
+    pub fn alternates(&self) -> Option<u32> {
+        self.alternates
+    }
+
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    pub fn top_p(&self) -> Option<f32> {
+        self.top_p
+    }
+
+    pub fn presence_penalty(&self) -> Option<f32> {
+        self.presence_penalty
+    }
+
+    pub fn frequency_penalty(&self) -> Option<f32> {
+        self.frequency_penalty
+    }
+
+    pub fn stop_sequences(&self) -> &[String] {
+        &self.stop_sequences
+    }
+
+    pub fn seed(&self) -> Option<i64> {
+        self.seed
+    }
+
+    pub fn provider(&self) -> Option<ProviderKind> {
+        self.provider
+    }
+
+    pub fn script(&self) -> Option<&Path> {
+        self.script.as_deref()
+    }
+
+    pub fn base_url(&self) -> Option<&str> {
+        self.base_url.as_deref()
+    }
+}
@@ -0,0 +1,748 @@
+mod migrations;
+
+use std::path::Path;
+
+use crate::{
+    message::{branch, Alternate, Alternates, ConversationSummary, Message, SearchHit, TokenUsage},
+    sampling::SamplingParameters,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use tracing::info;
+
+/// Open the database file, creating it if it doesn't exist yet.
+///
+/// Messages are written to the file as soon as they're sent, so the database runs in WAL mode to
+/// keep those writes quick and to make sure a crash can't leave it half-written.
+pub fn open_database(path: &Path) -> Result<Connection, anyhow::Error> {
+    let mut conn = Connection::open(path)
+        .with_context(|| format!("failed to open database at {}", path.display()))?;
+
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+        .context("enabling write-ahead logging")?;
+    migrations::migrate(&mut conn)?;
+
+    Ok(conn)
+}
+
+/// Load the most recently started conversation, if there is one. Only the branch of it that was
+/// added to most recently is loaded.
+pub fn load_previous_conversation(
+    conn: &Connection,
+) -> Result<Option<(i64, Vec<Message>)>, anyhow::Error> {
+    let id = match conn.query_row(
+        "SELECT id FROM conversations ORDER BY created_at DESC, id DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    ) {
+        Ok(id) => id,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            info!("there's no previous conversation to load");
+            return Ok(None);
+        }
+        Err(e) => return Err(e).context("failed to load previous conversation ID from database"),
+    };
+
+    get_newest_branch(conn, id).map(|messages| Some((id, messages)))
+}
+
+/// Summarize every saved conversation, most recent first.
+pub fn list_conversations(conn: &Connection) -> Result<Vec<ConversationSummary>, anyhow::Error> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT c.id, c.created_at, c.prompt, COUNT(m.id),
+                (SELECT content FROM messages WHERE conversation = c.id ORDER BY id LIMIT 1)
+            FROM conversations c
+            LEFT JOIN messages m ON m.conversation = c.id
+            GROUP BY c.id
+            ORDER BY c.created_at DESC, c.id DESC",
+        )
+        .context("preparing statement to list conversations")?;
+    let rows = stmt
+        .query_map([], conversation_summary_from_row)
+        .context("failed to list conversations")?;
+
+    rows.collect::<Result<_, _>>()
+        .context("failed to list conversations")
+}
+
+/// Summarize one saved conversation, or return `None` if there's no conversation with that ID.
+pub fn get_conversation_summary(
+    conn: &Connection,
+    conversation_id: i64,
+) -> Result<Option<ConversationSummary>, anyhow::Error> {
+    match conn.query_row(
+        "SELECT c.id, c.created_at, c.prompt, COUNT(m.id),
+            (SELECT content FROM messages WHERE conversation = c.id ORDER BY id LIMIT 1)
+        FROM conversations c
+        LEFT JOIN messages m ON m.conversation = c.id
+        WHERE c.id = ?1
+        GROUP BY c.id",
+        [conversation_id],
+        conversation_summary_from_row,
+    ) {
+        Ok(summary) => Ok(Some(summary)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e).context("failed to load conversation summary"),
+    }
+}
+
+fn conversation_summary_from_row(row: &rusqlite::Row) -> rusqlite::Result<ConversationSummary> {
+    Ok(ConversationSummary {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        prompt: row.get(2)?,
+        message_count: row.get(3)?,
+        first_message: row.get(4)?,
+    })
+}
+
+/// Find the messages containing every word of `query`, across all conversations. The best
+/// matches come first.
+pub fn search_messages(
+    conn: &Connection,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<SearchHit>, anyhow::Error> {
+    let query = fts_query(query);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT m.conversation, m.id, m.sender, m.created_at,
+                snippet(messages_fts, 0, char(2), char(3), '…', 12)
+            FROM messages_fts
+            JOIN messages m ON m.id = messages_fts.rowid
+            WHERE messages_fts MATCH ?1
+            ORDER BY rank
+            LIMIT ?2",
+        )
+        .context("preparing statement to search messages")?;
+    let rows = stmt
+        .query_map(params![query, limit], |row| {
+            Ok(SearchHit {
+                conversation_id: row.get(0)?,
+                message_id: row.get(1)?,
+                sender: row.get(2)?,
+                timestamp: row.get(3)?,
+                snippet: row.get(4)?,
+            })
+        })
+        .context("failed to search messages")?;
+
+    rows.collect::<Result<_, _>>()
+        .context("failed to search messages")
+}
+
+// FTS5 has its own query language, and plenty of things people type (like apostrophes) are syntax
+// errors in it. Quoting each word makes them all plain words again.
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Start a new conversation, returning its ID.
+pub fn insert_conversation(
+    conn: &Connection,
+    prompt: &str,
+    created_at: DateTime<Utc>,
+) -> Result<i64, anyhow::Error> {
+    conn.execute(
+        "INSERT INTO conversations (created_at, prompt) VALUES (?1, ?2)",
+        (created_at, prompt),
+    )
+    .context("inserting conversation into database")?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Save a message as part of a conversation, returning the ID it was saved with.
+pub fn insert_message(
+    conn: &Connection,
+    conversation_id: i64,
+    message: &Message,
+) -> Result<u64, anyhow::Error> {
+    conn.execute(
+        "INSERT INTO messages (sender, content, created_at, conversation, prompt_tokens, completion_tokens, parent, sampling, truncated)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            message.sender,
+            message.content,
+            message.timestamp,
+            conversation_id,
+            message.usage.map(|usage| usage.prompt_tokens),
+            message.usage.map(|usage| usage.completion_tokens),
+            message.parent,
+            sampling_to_json(message.sampling.as_ref())?,
+            message.truncated,
+        ],
+    )
+    .context("inserting message into database")?;
+
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+/// Save a regenerated message's alternates, giving the new ones their IDs, and make the chosen one
+/// the message's content.
+pub fn save_alternates(conn: &Connection, message: &mut Message) -> Result<(), anyhow::Error> {
+    let Some(alternates) = &mut message.alternates else {
+        return Ok(());
+    };
+
+    for alternate in alternates.responses.iter_mut().filter(|a| a.id == 0) {
+        conn.execute(
+            "INSERT INTO alternates (message, content, created_at, sampling, truncated)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                message.id,
+                alternate.content,
+                alternate.timestamp,
+                sampling_to_json(alternate.sampling.as_ref())?,
+                alternate.truncated,
+            ],
+        )
+        .context("inserting alternate into database")?;
+        alternate.id = conn.last_insert_rowid() as u64;
+    }
+
+    conn.execute(
+        "UPDATE messages
+            SET content = ?1, created_at = ?2, prompt_tokens = ?3, completion_tokens = ?4,
+                alternate = ?5, sampling = ?6, truncated = ?7
+            WHERE id = ?8",
+        params![
+            message.content,
+            message.timestamp,
+            message.usage.map(|usage| usage.prompt_tokens),
+            message.usage.map(|usage| usage.completion_tokens),
+            alternates.responses[alternates.chosen].id,
+            sampling_to_json(message.sampling.as_ref())?,
+            message.truncated,
+            message.id,
+        ],
+    )
+    .context("updating message with its chosen alternate")?;
+
+    Ok(())
+}
+
+/// Save a message that the bot has carried on writing, along with the alternate it's showing.
+pub fn save_continuation(conn: &Connection, message: &Message) -> Result<(), anyhow::Error> {
+    conn.execute(
+        "UPDATE messages
+            SET content = ?1, prompt_tokens = ?2, completion_tokens = ?3, truncated = ?4
+            WHERE id = ?5",
+        params![
+            message.content,
+            message.usage.map(|usage| usage.prompt_tokens),
+            message.usage.map(|usage| usage.completion_tokens),
+            message.truncated,
+            message.id,
+        ],
+    )
+    .context("updating continued message")?;
+
+    if let Some(alternates) = &message.alternates {
+        let chosen = &alternates.responses[alternates.chosen];
+        conn.execute(
+            "UPDATE alternates SET content = ?1, truncated = ?2 WHERE id = ?3",
+            params![chosen.content, chosen.truncated, chosen.id],
+        )
+        .context("updating continued alternate")?;
+    }
+
+    Ok(())
+}
+
+/// Save a conversation that came from somewhere else, unless one with the same external ID has
+/// already been imported. Returns the new conversation's ID, or `None` if it was already there.
+pub fn insert_imported_conversation(
+    conn: &mut Connection,
+    external_id: &str,
+    prompt: &str,
+    messages: &[Message],
+) -> Result<Option<i64>, anyhow::Error> {
+    let tx = conn.transaction().context("starting transaction")?;
+    let exists: bool = tx
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM conversations WHERE external_id = ?1)",
+            [external_id],
+            |row| row.get(0),
+        )
+        .context("checking for imported conversation")?;
+    if exists {
+        return Ok(None);
+    }
+
+    let created_at = messages.first().map_or_else(Utc::now, |m| m.timestamp);
+    tx.execute(
+        "INSERT INTO conversations (created_at, prompt, external_id) VALUES (?1, ?2, ?3)",
+        params![created_at, prompt, external_id],
+    )
+    .context("inserting imported conversation into database")?;
+    let id = tx.last_insert_rowid();
+    // Imported conversations don't branch, so each message follows the one before it
+    let mut parent = None;
+    for message in messages {
+        let message = Message {
+            parent,
+            ..message.clone()
+        };
+        parent = Some(insert_message(&tx, id, &message)?);
+    }
+    tx.commit().context("committing transaction")?;
+
+    Ok(Some(id))
+}
+
+pub fn delete_message(conn: &Connection, id: u64) -> Result<(), anyhow::Error> {
+    conn.execute("DELETE FROM messages WHERE id = ?1", [id])
+        .context("deleting message from database")?;
+
+    Ok(())
+}
+
+/// Delete a conversation that doesn't have any messages left.
+pub fn delete_conversation(conn: &Connection, id: i64) -> Result<(), anyhow::Error> {
+    conn.execute("DELETE FROM conversations WHERE id = ?1", [id])
+        .context("deleting conversation from database")?;
+
+    Ok(())
+}
+
+/// Load the branch of a conversation that was added to most recently, from its first message to
+/// its last.
+pub fn get_newest_branch(
+    conn: &Connection,
+    conversation_id: i64,
+) -> Result<Vec<Message>, anyhow::Error> {
+    get_messages_by_conversation_id(conn, conversation_id)
+        .map(|messages| branch::newest_branch(&messages))
+}
+
+/// Load every message of a conversation from every one of its branches, oldest first.
+pub fn get_messages_by_conversation_id(
+    conn: &Connection,
+    conversation_id: i64,
+) -> Result<Vec<Message>, anyhow::Error> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM conversations WHERE id = ?1)",
+            [conversation_id],
+            |row| row.get(0),
+        )
+        .context("failed to look up conversation")?;
+    if !exists {
+        anyhow::bail!("there's no conversation with ID {conversation_id}");
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, sender, content, created_at, conversation, prompt_tokens, completion_tokens,
+                alternate, parent, sampling, truncated
+            FROM messages
+            WHERE conversation = ?1
+            ORDER BY id
+        ",
+    )?;
+    let rows = stmt
+        .query_map([conversation_id], |row| {
+            let message = Message {
+                id: row.get(0)?,
+                sender: row.get(1)?,
+                content: row.get(2)?,
+                timestamp: row.get(3)?,
+                usage: match (row.get(5)?, row.get(6)?) {
+                    (Some(prompt_tokens), Some(completion_tokens)) => Some(TokenUsage {
+                        prompt_tokens,
+                        completion_tokens,
+                    }),
+                    _ => None,
+                },
+                alternates: None,
+                parent: row.get(8)?,
+                siblings: None,
+                sampling: None,
+                truncated: row.get(10)?,
+            };
+
+            Ok((
+                message,
+                row.get::<_, Option<u64>>(7)?,
+                row.get::<_, Option<String>>(9)?,
+            ))
+        })
+        .context("failed to load messages from database")?;
+
+    // TODO is there a fancier way to do this with a `collect()`?
+    let mut messages = Vec::new();
+    for row in rows {
+        let (mut message, chosen_alternate, sampling) = row?;
+        message.sampling = sampling_from_json(sampling.as_deref())?;
+        if let Some(chosen) = chosen_alternate {
+            message.alternates = Some(get_alternates(conn, message.id, chosen)?);
+        }
+        messages.push(message);
+    }
+
+    Ok(messages)
+}
+
+// Only messages that have been regenerated have alternates, so they're looked up one message at a
+// time instead of joined in.
+fn get_alternates(
+    conn: &Connection,
+    message_id: u64,
+    chosen_id: u64,
+) -> Result<Alternates, anyhow::Error> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, content, created_at, sampling, truncated
+                FROM alternates WHERE message = ?1 ORDER BY id",
+        )
+        .context("preparing statement to load alternates")?;
+    let rows = stmt
+        .query_map([message_id], |row| {
+            let alternate = Alternate {
+                id: row.get(0)?,
+                content: row.get(1)?,
+                timestamp: row.get(2)?,
+                sampling: None,
+                truncated: row.get(4)?,
+            };
+
+            Ok((alternate, row.get::<_, Option<String>>(3)?))
+        })
+        .context("failed to load alternates from database")?;
+    let mut responses = Vec::new();
+    for row in rows {
+        let (mut alternate, sampling) = row.context("failed to load alternates from database")?;
+        alternate.sampling = sampling_from_json(sampling.as_deref())?;
+        responses.push(alternate);
+    }
+    let chosen = responses
+        .iter()
+        .position(|alternate| alternate.id == chosen_id)
+        .with_context(|| format!("message {message_id}'s chosen alternate is missing"))?;
+
+    Ok(Alternates { responses, chosen })
+}
+
+// Sampling parameters are saved as JSON, since most of them are usually left unset and there are
+// a few stop sequences to a message at most
+fn sampling_to_json(
+    sampling: Option<&SamplingParameters>,
+) -> Result<Option<String>, anyhow::Error> {
+    sampling
+        .map(serde_json::to_string)
+        .transpose()
+        .context("serializing sampling parameters")
+}
+
+fn sampling_from_json(json: Option<&str>) -> Result<Option<SamplingParameters>, anyhow::Error> {
+    json.map(serde_json::from_str)
+        .transpose()
+        .context("reading saved sampling parameters")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::test_message;
+    use pretty_assertions::assert_eq;
+    use std::{thread, time::Duration};
+
+    const USER_NAME: &str = "test_user";
+    const BOT_NAME: &str = "test_bot";
+    const PROMPT: &str = "test prompt";
+    const DB_PATH: &str = "test.db";
+
+    fn load_test_conversation() -> Vec<Message> {
+        let contents = [
+            (USER_NAME, "Hello bot."),
+            (BOT_NAME, "Hello user."),
+            (USER_NAME, "How are you?"),
+            (BOT_NAME, "I'm fine, thanks. How are you?"),
+            (USER_NAME, "I'm fine too. Goodbye for now, bot."),
+            (BOT_NAME, "Goodbye user."),
+        ];
+        let mut messages = Vec::new();
+        for (i, (sender, content)) in contents.into_iter().enumerate() {
+            if i > 0 {
+                // These sleeps ensure the timestamps will be different
+                thread::sleep(Duration::from_millis(100));
+            }
+            messages.push(test_message(i as u64 + 1, sender, content));
+        }
+        messages[1].usage = Some(TokenUsage {
+            prompt_tokens: 25,
+            completion_tokens: 4,
+        });
+
+        messages
+    }
+
+    // Save a message the way the app did before messages had anything more than these columns
+    fn insert_old_message(conn: &Connection, conversation_id: i64, message: &Message) {
+        conn.execute(
+            "INSERT INTO messages (sender, content, created_at, conversation)
+                VALUES (?1, ?2, ?3, ?4)",
+            params![
+                message.sender,
+                message.content,
+                message.timestamp,
+                conversation_id
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_e2e() {
+        let db_path = Path::new(DB_PATH);
+
+        // Save a test conversation one message at a time, the same way the app does.
+        let conn = open_database(db_path).unwrap();
+        assert_eq!(load_previous_conversation(&conn).unwrap(), None);
+        let messages = load_test_conversation();
+        let conversation_id = insert_conversation(&conn, PROMPT, messages[0].timestamp).unwrap();
+        for message in &messages {
+            assert_eq!(
+                insert_message(&conn, conversation_id, message).unwrap(),
+                message.id
+            );
+        }
+        // Nothing is saved on the way out, so it doesn't matter how the connection goes away.
+        drop(conn);
+
+        // Load the DB from disk and make sure the conversation matches the test conversation.
+        let conn = open_database(db_path).unwrap();
+        let (id, messages_from_db) = load_previous_conversation(&conn).unwrap().unwrap();
+        assert_eq!(id, conversation_id);
+        assert_eq!(messages, messages_from_db);
+
+        let conversations = list_conversations(&conn).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].message_count, messages.len());
+        assert_eq!(
+            conversations[0].first_message.as_deref(),
+            Some("Hello bot.")
+        );
+        let summary = get_conversation_summary(&conn, id).unwrap().unwrap();
+        assert_eq!(summary.message_count, messages.len());
+        assert_eq!(summary.prompt, PROMPT);
+        assert!(get_conversation_summary(&conn, id + 1).unwrap().is_none());
+        assert_eq!(
+            messages.iter().map(|m| m.usage).collect::<Vec<_>>(),
+            messages_from_db.iter().map(|m| m.usage).collect::<Vec<_>>()
+        );
+        conn.close().unwrap();
+
+        // Clean up the DB file for future tests.
+        std::fs::remove_file(DB_PATH).unwrap();
+        let _ = std::fs::remove_file(format!("{DB_PATH}-wal"));
+        let _ = std::fs::remove_file(format!("{DB_PATH}-shm"));
+    }
+
+    #[test]
+    fn test_duplicated_conversations_are_merged() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // Up to just before the merge
+        migrations::migrate_through(&mut conn, 3).unwrap();
+
+        let messages = load_test_conversation();
+        let created_at = messages[0].timestamp;
+        // The original conversation, and two copies of it made by resuming it twice
+        for len in [2, 4, 6] {
+            let id = insert_conversation(&conn, PROMPT, created_at).unwrap();
+            for message in &messages[..len] {
+                insert_old_message(&conn, id, message);
+            }
+        }
+        // A conversation that was started separately but happens to begin the same way
+        let unrelated_id = insert_conversation(&conn, "another prompt", created_at).unwrap();
+        insert_old_message(&conn, unrelated_id, &messages[0]);
+
+        migrations::migrate(&mut conn).unwrap();
+
+        let remaining: Vec<i64> = conn
+            .prepare("SELECT id FROM conversations ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(remaining, vec![3, unrelated_id]);
+
+        let contents: Vec<_> = get_messages_by_conversation_id(&conn, 3)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.content)
+            .collect();
+        assert_eq!(
+            contents,
+            messages
+                .iter()
+                .map(|m| m.content.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_search_finds_messages_across_conversations() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // Up to just before the search index
+        migrations::migrate_through(&mut conn, 2).unwrap();
+
+        let messages = load_test_conversation();
+        // Messages saved before the index existed have to be found too
+        let first_id = insert_conversation(&conn, PROMPT, messages[0].timestamp).unwrap();
+        for message in &messages[..4] {
+            insert_old_message(&conn, first_id, message);
+        }
+        migrations::migrate(&mut conn).unwrap();
+        let second_id = insert_conversation(&conn, PROMPT, messages[4].timestamp).unwrap();
+        for message in &messages[4..] {
+            insert_message(&conn, second_id, message).unwrap();
+        }
+
+        let hits = search_messages(&conn, "I'm fine", 10).unwrap();
+        let mut found: Vec<_> = hits
+            .iter()
+            .map(|hit| (hit.conversation_id, hit.message_id))
+            .collect();
+        found.sort();
+        assert_eq!(found, vec![(first_id, 4), (second_id, 5)]);
+        assert!(hits
+            .iter()
+            .any(|hit| hit.snippet.contains("\u{2}fine\u{3}")));
+
+        // Deleted messages are removed from the index. Only the last message of a branch can be
+        // deleted, since the rest have messages following them.
+        delete_message(&conn, 6).unwrap();
+        let hits = search_messages(&conn, "goodbye", 10).unwrap();
+        assert_eq!(
+            hits.iter().map(|hit| hit.message_id).collect::<Vec<_>>(),
+            vec![5]
+        );
+
+        assert!(search_messages(&conn, "  ", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_imported_conversations_are_only_saved_once() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        let messages = load_test_conversation();
+
+        let id = insert_imported_conversation(&mut conn, "chatgpt:abc", PROMPT, &messages)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            insert_imported_conversation(&mut conn, "chatgpt:abc", PROMPT, &messages).unwrap(),
+            None
+        );
+
+        let conversations = list_conversations(&conn).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].created_at, messages[0].timestamp);
+        let contents: Vec<_> = get_messages_by_conversation_id(&conn, id)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.content)
+            .collect();
+        assert_eq!(
+            contents,
+            messages.into_iter().map(|m| m.content).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_alternates_are_saved_with_the_chosen_one() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        let messages = load_test_conversation();
+        let id = insert_conversation(&conn, PROMPT, messages[0].timestamp).unwrap();
+        for message in &messages {
+            insert_message(&conn, id, message).unwrap();
+        }
+
+        let mut regenerated = messages[5].clone();
+        regenerated.add_alternates(Message {
+            content: "See you later, user.".to_string(),
+            ..messages[5].clone()
+        });
+        save_alternates(&conn, &mut regenerated).unwrap();
+        regenerated.cycle_alternates(false);
+        save_alternates(&conn, &mut regenerated).unwrap();
+
+        let saved = get_messages_by_conversation_id(&conn, id)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(saved.content, "Goodbye user.");
+        let alternates = saved.alternates.unwrap();
+        assert_eq!(alternates.chosen, 0);
+        assert_eq!(
+            alternates
+                .responses
+                .iter()
+                .map(|a| a.content.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Goodbye user.", "See you later, user."]
+        );
+        // The alternates were only saved once
+        let count: u64 = conn
+            .query_row("SELECT COUNT(*) FROM alternates", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_sampling_parameters_are_saved_with_each_response() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        let mut messages = load_test_conversation();
+        let focused = SamplingParameters {
+            temperature: Some(0.2),
+            stop: vec!["\ntest_user:".to_owned()],
+            ..Default::default()
+        };
+        let creative = SamplingParameters {
+            temperature: Some(1.3),
+            seed: Some(42),
+            ..Default::default()
+        };
+        messages[5].record_sampling(&focused);
+        let id = insert_conversation(&conn, PROMPT, messages[0].timestamp).unwrap();
+        for message in &messages {
+            insert_message(&conn, id, message).unwrap();
+        }
+
+        let mut response = Message {
+            content: "See you later, user.".to_string(),
+            ..messages[5].clone()
+        };
+        response.record_sampling(&creative);
+        messages[5].add_alternates(response);
+        save_alternates(&conn, &mut messages[5]).unwrap();
+
+        let mut saved = get_messages_by_conversation_id(&conn, id)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(saved.sampling.as_ref(), Some(&creative));
+        saved.cycle_alternates(false);
+        assert_eq!(saved.sampling.as_ref(), Some(&focused));
+        // The user's messages weren't asked for with anything
+        let first = get_messages_by_conversation_id(&conn, id)
+            .unwrap()
+            .remove(0);
+        assert_eq!(first.sampling, None);
+    }
+}
@@ -0,0 +1,30 @@
+-- A database saved before there were migrations. Conversation 2 is a copy of conversation 1 made
+-- by resuming it, which that version of the app did.
+CREATE TABLE conversations (
+    id         INTEGER PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    prompt     TEXT NOT NULL
+);
+
+CREATE TABLE messages (
+    id                        INTEGER PRIMARY KEY,
+    sender                    TEXT NOT NULL,
+    content                   TEXT NOT NULL,
+    created_at                TEXT NOT NULL,
+    conversation              INTEGER NOT NULL,
+    FOREIGN KEY(conversation) REFERENCES conversations(id)
+);
+
+INSERT INTO conversations (id, created_at, prompt) VALUES
+    (1, '2023-04-02 18:30:00.000+00:00', 'You are a helpful assistant.'),
+    (2, '2023-04-02 18:30:00.000+00:00', 'You are a helpful assistant.'),
+    (3, '2023-04-03 09:15:00.000+00:00', 'You are a pirate.');
+
+INSERT INTO messages (id, sender, content, created_at, conversation) VALUES
+    (1, 'user', 'What is a lighthouse for?', '2023-04-02 18:30:00.000+00:00', 1),
+    (2, 'bot', 'It warns ships away from the rocks.', '2023-04-02 18:30:05.000+00:00', 1),
+    (3, 'user', 'What is a lighthouse for?', '2023-04-02 18:30:00.000+00:00', 2),
+    (4, 'bot', 'It warns ships away from the rocks.', '2023-04-02 18:30:05.000+00:00', 2),
+    (5, 'user', 'Who keeps it running?', '2023-04-04 10:00:00.000+00:00', 2),
+    (6, 'bot', 'A lighthouse keeper, traditionally.', '2023-04-04 10:00:04.000+00:00', 2),
+    (7, 'user', 'Ahoy!', '2023-04-03 09:15:00.000+00:00', 3);
@@ -0,0 +1,26 @@
+-- A database at version 2, which tracks token usage but doesn't have a search index yet.
+CREATE TABLE conversations (
+    id         INTEGER PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    prompt     TEXT NOT NULL
+);
+
+CREATE TABLE messages (
+    id                        INTEGER PRIMARY KEY,
+    sender                    TEXT NOT NULL,
+    content                   TEXT NOT NULL,
+    created_at                TEXT NOT NULL,
+    conversation              INTEGER NOT NULL,
+    prompt_tokens             INTEGER,
+    completion_tokens         INTEGER,
+    FOREIGN KEY(conversation) REFERENCES conversations(id)
+);
+
+INSERT INTO conversations (id, created_at, prompt) VALUES
+    (1, '2023-06-10 08:00:00.000+00:00', 'You are a helpful assistant.');
+
+INSERT INTO messages (id, sender, content, created_at, conversation, prompt_tokens, completion_tokens) VALUES
+    (1, 'user', 'How tall is a giraffe?', '2023-06-10 08:00:00.000+00:00', 1, NULL, NULL),
+    (2, 'bot', 'Adult giraffes are around five metres tall.', '2023-06-10 08:00:03.000+00:00', 1, 30, 11);
+
+PRAGMA user_version = 2;
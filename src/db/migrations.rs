@@ -0,0 +1,377 @@
+//! Every change to the database's schema, in order, so that databases saved by older versions of
+//! the app can be upgraded in place.
+//!
+//! SQLite's `user_version` pragma holds how many of the migrations a database has had applied. A
+//! fresh database starts at 0 and goes through all of them. Migrations are never edited or
+//! reordered once they've been released, only added to the end.
+
+use anyhow::Context;
+use rusqlite::Connection;
+use tracing::{debug, info};
+
+type Migration = fn(&Connection) -> Result<(), anyhow::Error>;
+
+const MIGRATIONS: &[(&str, Migration)] = &[
+    ("create tables", create_tables),
+    ("add token usage columns", add_token_usage_columns),
+    ("create search index", create_search_index),
+    (
+        "merge duplicated conversations",
+        merge_duplicated_conversations,
+    ),
+    ("add external IDs to conversations", add_external_ids),
+    ("add alternate responses", add_alternates),
+    ("add parents to messages", add_message_parents),
+    ("record sampling parameters", add_sampling_columns),
+    ("mark truncated responses", add_truncated_columns),
+];
+
+/// Apply any migrations the database hasn't had yet. Each one runs in its own transaction along
+/// with bumping the version, so a failed migration leaves the database as it was before it.
+pub(super) fn migrate(conn: &mut Connection) -> Result<(), anyhow::Error> {
+    migrate_through(conn, MIGRATIONS.len())
+}
+
+/// Apply migrations up to and including the `last`th one. Tests use this to set up a database from
+/// an older version of the app.
+pub(super) fn migrate_through(conn: &mut Connection, last: usize) -> Result<(), anyhow::Error> {
+    let version: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .context("reading database version")?;
+    if version > MIGRATIONS.len() {
+        anyhow::bail!(
+            "the database is from a newer version of the app (schema version {version}, but this \
+            version only knows about {}). Update the app to use it.",
+            MIGRATIONS.len()
+        );
+    }
+
+    for (i, (description, migration)) in MIGRATIONS.iter().enumerate().take(last).skip(version) {
+        info!("migrating database to version {}: {description}", i + 1);
+        let tx = conn.transaction().context("starting transaction")?;
+        migration(&tx).with_context(|| format!("failed to {description}"))?;
+        tx.pragma_update(None, "user_version", i + 1)
+            .context("updating database version")?;
+        tx.commit().context("committing transaction")?;
+    }
+
+    Ok(())
+}
+
+// Databases from before there were migrations are still at version 0, but already have these
+// tables. That's why this doesn't fail if they exist.
+fn create_tables(conn: &Connection) -> Result<(), anyhow::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS conversations (
+            id         INTEGER PRIMARY KEY,
+            created_at TEXT NOT NULL,
+            prompt     TEXT NOT NULL
+        )",
+        (),
+    )
+    .context("creating conversations table")?;
+
+    // Messages keep track of their conversation instead of the other way around. Is that really
+    // stupid?
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS messages (
+            id                        INTEGER PRIMARY KEY,
+            sender                    TEXT NOT NULL,
+            content                   TEXT NOT NULL,
+            created_at                TEXT NOT NULL,
+            conversation              INTEGER NOT NULL,
+            FOREIGN KEY(conversation) REFERENCES conversations(id)
+        )",
+        (),
+    )
+    .context("creating messages table")?;
+
+    Ok(())
+}
+
+// Message contents are indexed for full-text search. Triggers keep the index up to date with the
+// messages table, and the index is filled in with any existing messages the first time it's made.
+fn create_search_index(conn: &Connection) -> Result<(), anyhow::Error> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'messages_fts')",
+            [],
+            |row| row.get(0),
+        )
+        .context("checking for search index")?;
+    if exists {
+        return Ok(());
+    }
+
+    debug!("creating search index");
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE messages_fts USING fts5(
+            content,
+            content = 'messages',
+            content_rowid = 'id'
+        );
+
+        CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+
+        CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content)
+                VALUES ('delete', old.id, old.content);
+        END;
+
+        CREATE TRIGGER messages_fts_update AFTER UPDATE ON messages BEGIN
+            INSERT INTO messages_fts (messages_fts, rowid, content)
+                VALUES ('delete', old.id, old.content);
+            INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+        END;
+
+        INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');",
+    )
+    .context("creating search index")
+}
+
+// Databases saved before token usage was tracked don't have columns for it. Messages from back then
+// just won't have any usage. Some databases from before there were migrations already have them,
+// so only the missing ones are added.
+fn add_token_usage_columns(conn: &Connection) -> Result<(), anyhow::Error> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM pragma_table_info('messages')")
+        .context("reading columns of messages table")?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()
+        .context("reading columns of messages table")?;
+
+    for column in ["prompt_tokens", "completion_tokens"] {
+        if !columns.iter().any(|c| c == column) {
+            debug!("adding {column} column to messages table");
+            conn.execute(
+                &format!("ALTER TABLE messages ADD COLUMN {column} INTEGER"),
+                (),
+            )
+            .with_context(|| format!("adding {column} column to messages table"))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Resuming a conversation used to save it all over again as a new conversation once the app quit,
+// copying every message of the original. Each copy starts with the exact same messages as the
+// conversation it was resumed from, so only the longest one needs to be kept.
+//
+// Before there were migrations, the merge set the database's version to 1 once it had been done.
+// Those databases already have their tables and go through the rest of the migrations from there,
+// so this runs on them again but finds nothing left to merge.
+fn merge_duplicated_conversations(tx: &Connection) -> Result<(), anyhow::Error> {
+    // Copies were created with the timestamp of the original's first message, so they sort
+    // right after the original.
+    let conversations = {
+        let mut stmt = tx
+            .prepare("SELECT id, created_at, prompt FROM conversations ORDER BY created_at, id")
+            .context("preparing statement to load conversations")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .context("loading conversations from database")?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+
+    for pair in conversations.windows(2) {
+        let (original_id, original_created_at, original_prompt) = &pair[0];
+        let (copy_id, copy_created_at, copy_prompt) = &pair[1];
+        if original_created_at != copy_created_at || original_prompt != copy_prompt {
+            continue;
+        }
+
+        let original = load_messages(tx, *original_id)?;
+        let copy = load_messages(tx, *copy_id)?;
+        let is_copy =
+            original.len() <= copy.len() && original.iter().zip(&copy).all(|(a, b)| a == b);
+
+        if is_copy {
+            info!("merging conversation {original_id} into its copy, conversation {copy_id}");
+            tx.execute(
+                "DELETE FROM messages WHERE conversation = ?1",
+                [original_id],
+            )
+            .context("deleting duplicated messages")?;
+            tx.execute("DELETE FROM conversations WHERE id = ?1", [original_id])
+                .context("deleting duplicated conversation")?;
+        }
+    }
+
+    Ok(())
+}
+
+// The sender, content, and timestamp of each of a conversation's messages. Migrations can't use
+// `get_messages_by_conversation_id`, since it expects the columns that later migrations add.
+fn load_messages(
+    tx: &Connection,
+    conversation_id: i64,
+) -> Result<Vec<(String, String, String)>, anyhow::Error> {
+    let mut stmt = tx
+        .prepare(
+            "SELECT sender, content, created_at FROM messages WHERE conversation = ?1 ORDER BY id",
+        )
+        .context("preparing statement to load messages")?;
+    let rows = stmt
+        .query_map([conversation_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .context("loading messages from database")?;
+
+    rows.collect::<Result<_, _>>()
+        .context("loading messages from database")
+}
+
+// Imported conversations remember where they came from, so importing the same file twice doesn't
+// duplicate them. Conversations started in the app don't have one.
+fn add_external_ids(conn: &Connection) -> Result<(), anyhow::Error> {
+    conn.execute_batch(
+        "ALTER TABLE conversations ADD COLUMN external_id TEXT;
+        CREATE UNIQUE INDEX conversations_external_id ON conversations (external_id);",
+    )
+    .context("adding external_id column to conversations table")
+}
+
+// Regenerated responses are kept around so the user can switch between them. The one they chose is
+// also the message's content, so nothing else has to know about alternates.
+fn add_alternates(conn: &Connection) -> Result<(), anyhow::Error> {
+    conn.execute_batch(
+        "CREATE TABLE alternates (
+            id                   INTEGER PRIMARY KEY,
+            message              INTEGER NOT NULL,
+            content              TEXT NOT NULL,
+            created_at           TEXT NOT NULL,
+            FOREIGN KEY(message) REFERENCES messages(id)
+        );
+        CREATE INDEX alternates_message ON alternates (message);
+        ALTER TABLE messages ADD COLUMN alternate INTEGER REFERENCES alternates(id);",
+    )
+    .context("creating alternates table")
+}
+
+// Messages point at the message they follow, so that conversations can branch. Until now they've
+// all followed the message before them in the same conversation.
+fn add_message_parents(conn: &Connection) -> Result<(), anyhow::Error> {
+    conn.execute_batch(
+        "ALTER TABLE messages ADD COLUMN parent INTEGER REFERENCES messages(id);
+        UPDATE messages SET parent = (
+            SELECT MAX(previous.id) FROM messages previous
+            WHERE previous.conversation = messages.conversation AND previous.id < messages.id
+        );",
+    )
+    .context("adding parent column to messages table")
+}
+
+// What the bot's responses were asked for with, as JSON. Older responses don't have any.
+fn add_sampling_columns(conn: &Connection) -> Result<(), anyhow::Error> {
+    conn.execute_batch(
+        "ALTER TABLE messages ADD COLUMN sampling TEXT;
+        ALTER TABLE alternates ADD COLUMN sampling TEXT;",
+    )
+    .context("adding sampling columns")
+}
+
+// Whether the bot ran out of tokens partway through a response. Older responses are assumed to be
+// finished, since there's no telling.
+fn add_truncated_columns(conn: &Connection) -> Result<(), anyhow::Error> {
+    conn.execute_batch(
+        "ALTER TABLE messages ADD COLUMN truncated INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE alternates ADD COLUMN truncated INTEGER NOT NULL DEFAULT 0;",
+    )
+    .context("adding truncated columns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db, message::TokenUsage};
+    use pretty_assertions::assert_eq;
+
+    fn open_fixture(sql: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(sql).unwrap();
+        conn
+    }
+
+    fn version(conn: &Connection) -> usize {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_database_from_before_migrations_is_upgraded() {
+        let mut conn = open_fixture(include_str!("fixtures/v0.sql"));
+        assert_eq!(version(&conn), 0);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(version(&conn), MIGRATIONS.len());
+
+        // The resumed copy replaced the original
+        let ids: Vec<_> = db::list_conversations(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, vec![3, 2]);
+
+        let messages = db::get_messages_by_conversation_id(&conn, 2).unwrap();
+        assert_eq!(messages.len(), 4);
+        assert!(messages.iter().all(|m| m.usage.is_none()));
+
+        // Old messages are found by searches
+        let mut found: Vec<_> = db::search_messages(&conn, "lighthouse", 10)
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.message_id)
+            .collect();
+        found.sort();
+        assert_eq!(found, vec![3, 6]);
+
+        // Migrating an up to date database doesn't do anything
+        migrate(&mut conn).unwrap();
+        assert_eq!(version(&conn), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_database_is_upgraded_from_where_it_left_off() {
+        let mut conn = open_fixture(include_str!("fixtures/v2.sql"));
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(version(&conn), MIGRATIONS.len());
+
+        let messages = db::get_messages_by_conversation_id(&conn, 1).unwrap();
+        // Messages from before conversations could branch follow the one before them
+        assert_eq!(messages[0].parent, None);
+        assert_eq!(messages[1].parent, Some(messages[0].id));
+        assert_eq!(
+            messages.iter().map(|m| m.usage).collect::<Vec<_>>(),
+            vec![
+                None,
+                Some(TokenUsage {
+                    prompt_tokens: 30,
+                    completion_tokens: 11,
+                })
+            ]
+        );
+        assert_eq!(db::search_messages(&conn, "tall", 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_database_from_newer_version_is_left_alone() {
+        let mut conn = open_fixture(include_str!("fixtures/v2.sql"));
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        assert!(migrate(&mut conn).is_err());
+        assert_eq!(version(&conn), MIGRATIONS.len() + 1);
+    }
+}
@@ -0,0 +1,259 @@
+//! Writing saved conversations out in formats that are easy to share.
+
+use crate::{
+    db,
+    message::{ConversationSummary, Message},
+};
+use anyhow::Context;
+use clap::ValueEnum;
+use rusqlite::Connection;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A heading for each message, like the conversation looks in the app.
+    Markdown,
+    /// One JSON object per line: the prompt, then each message.
+    Json,
+    /// A standalone web page.
+    Html,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "jsonl",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+/// The name of the file a conversation is exported to when no other name is given.
+pub fn file_name(conversation_id: i64, format: ExportFormat) -> String {
+    format!("conversation-{conversation_id}.{}", format.extension())
+}
+
+/// Load a saved conversation and render it in `format`.
+pub fn export_conversation(
+    conn: &Connection,
+    conversation_id: i64,
+    format: ExportFormat,
+) -> Result<String, anyhow::Error> {
+    let summary = db::get_conversation_summary(conn, conversation_id)?
+        .with_context(|| format!("there's no conversation with ID {conversation_id}"))?;
+    let messages = db::get_newest_branch(conn, conversation_id)?;
+
+    render(format, &summary, &messages)
+}
+
+pub fn render(
+    format: ExportFormat,
+    summary: &ConversationSummary,
+    messages: &[Message],
+) -> Result<String, anyhow::Error> {
+    match format {
+        ExportFormat::Markdown => Ok(render_markdown(summary, messages)),
+        ExportFormat::Json => render_json(summary, messages),
+        ExportFormat::Html => Ok(render_html(summary, messages)),
+    }
+}
+
+// Each message's header is laid out the same as in the app: who sent it, when, and how many tokens
+// it took.
+fn message_header(message: &Message) -> String {
+    let mut header = message.timestamp.to_rfc2822();
+    if let Some(usage) = message.usage {
+        let _ = write!(header, " ({} tokens)", usage.total_tokens());
+    }
+
+    header
+}
+
+fn render_markdown(summary: &ConversationSummary, messages: &[Message]) -> String {
+    let mut out = format!(
+        "# Conversation {}\n\n*Started {}*\n\n> {}\n",
+        summary.id,
+        summary.created_at.to_rfc2822(),
+        summary.prompt.lines().collect::<Vec<_>>().join("\n> ")
+    );
+    for message in messages {
+        let _ = write!(
+            out,
+            "\n## {}\n\n*{}*\n\n{}\n",
+            message.sender,
+            message_header(message),
+            message.content.trim_end()
+        );
+    }
+
+    out
+}
+
+// The prompt goes first as a system message, the same as it's sent to chat models, so that it's
+// kept when the conversation is imported again.
+fn render_json(
+    summary: &ConversationSummary,
+    messages: &[Message],
+) -> Result<String, anyhow::Error> {
+    let mut out = String::new();
+    if !summary.prompt.is_empty() {
+        let prompt = serde_json::json!({ "role": "system", "content": summary.prompt });
+        out.push_str(&prompt.to_string());
+        out.push('\n');
+    }
+    for message in messages {
+        out.push_str(&serde_json::to_string(message).context("serializing message")?);
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+fn render_html(summary: &ConversationSummary, messages: &[Message]) -> String {
+    let mut out = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Conversation {id}</title>
+<style>
+  body {{ font-family: sans-serif; max-width: 48em; margin: 2em auto; padding: 0 1em; }}
+  .prompt {{ color: #555; border-left: 3px solid #ccc; padding-left: 1em; }}
+  .sender {{ margin-bottom: 0; }}
+  .timestamp {{ color: #777; font-style: italic; margin-top: 0.25em; }}
+  .content {{ white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<h1>Conversation {id}</h1>
+<p class="timestamp">Started {started}</p>
+<blockquote class="prompt">{prompt}</blockquote>
+"#,
+        id = summary.id,
+        started = summary.created_at.to_rfc2822(),
+        prompt = escape_html(&summary.prompt),
+    );
+    for message in messages {
+        let _ = write!(
+            out,
+            r#"<section class="message">
+<h2 class="sender">{}</h2>
+<p class="timestamp">{}</p>
+<div class="content">{}</div>
+</section>
+"#,
+            escape_html(&message.sender),
+            message_header(message),
+            escape_html(message.content.trim_end())
+        );
+    }
+    out.push_str("</body>\n</html>\n");
+
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{test_message, TokenUsage};
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    fn test_conversation() -> (ConversationSummary, Vec<Message>) {
+        let created_at = Utc.with_ymd_and_hms(2023, 4, 2, 18, 30, 0).unwrap();
+        let messages = vec![
+            Message {
+                timestamp: created_at,
+                ..test_message(1, "User", "Is 1 < 2?")
+            },
+            Message {
+                timestamp: created_at + chrono::Duration::seconds(3),
+                usage: Some(TokenUsage {
+                    prompt_tokens: 20,
+                    completion_tokens: 2,
+                }),
+                parent: None,
+                ..test_message(2, "Bot", "Yes.")
+            },
+        ];
+        let summary = ConversationSummary {
+            id: 7,
+            created_at,
+            prompt: "Be brief.".to_owned(),
+            message_count: messages.len(),
+            first_message: Some(messages[0].content.clone()),
+        };
+
+        (summary, messages)
+    }
+
+    #[test]
+    fn test_markdown_matches_the_app() {
+        let (summary, messages) = test_conversation();
+
+        assert_eq!(
+            render(ExportFormat::Markdown, &summary, &messages).unwrap(),
+            "# Conversation 7
+
+*Started Sun, 2 Apr 2023 18:30:00 +0000*
+
+> Be brief.
+
+## User
+
+*Sun, 2 Apr 2023 18:30:00 +0000*
+
+Is 1 < 2?
+
+## Bot
+
+*Sun, 2 Apr 2023 18:30:03 +0000 (22 tokens)*
+
+Yes.
+"
+        );
+    }
+
+    #[test]
+    fn test_json_has_a_line_per_message() {
+        let (summary, messages) = test_conversation();
+        let json = render(ExportFormat::Json, &summary, &messages).unwrap();
+
+        let lines: Vec<serde_json::Value> = json
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["role"], "system");
+        assert_eq!(lines[0]["content"], "Be brief.");
+        assert_eq!(lines[1]["content"], "Is 1 < 2?");
+        assert_eq!(lines[1]["usage"], serde_json::Value::Null);
+        assert_eq!(lines[2]["usage"]["completion_tokens"], 2);
+    }
+
+    #[test]
+    fn test_html_is_escaped() {
+        let (summary, messages) = test_conversation();
+        let html = render(ExportFormat::Html, &summary, &messages).unwrap();
+
+        assert!(html.contains(r#"<div class="content">Is 1 &lt; 2?</div>"#));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
@@ -0,0 +1,450 @@
+//! Reading conversations saved by other apps, so that they can be browsed, searched, and resumed
+//! like the ones started here.
+//!
+//! Two kinds of files are understood:
+//!
+//! - The `conversations.json` file from a ChatGPT data export.
+//! - JSON logs of messages. That's either one message per line (like `export --format json`
+//!   writes), an array of messages, or objects with a `messages` array (and optionally a `prompt`).
+//!   Messages need a `sender` (or `role`) and `content`, and can have a `timestamp`. The first
+//!   message from `system` is the conversation's prompt.
+
+use crate::message::{Message, TokenUsage};
+use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A conversation read from a file, ready to be saved.
+#[derive(Debug)]
+pub struct ImportedConversation {
+    /// Identifies where the conversation came from, so that it's only imported once.
+    pub external_id: String,
+    /// The conversation's system prompt, if it had one.
+    pub prompt: Option<String>,
+    pub messages: Vec<Message>,
+}
+
+/// Imported messages are attributed to a role like "user" or "assistant". These are who those
+/// roles become.
+pub struct Names<'a> {
+    pub user: &'a str,
+    pub bot: &'a str,
+}
+
+/// Parse every conversation in `text`, working out what kind of file it came from.
+pub fn parse(text: &str, names: &Names) -> Result<Vec<ImportedConversation>, anyhow::Error> {
+    let value = match serde_json::from_str::<Value>(text) {
+        Ok(value) => value,
+        // Not a single JSON document, so it had better be JSON lines
+        Err(_) => {
+            let messages = text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .enumerate()
+                .map(|(i, line)| {
+                    serde_json::from_str(line)
+                        .with_context(|| format!("line {} isn't a message", i + 1))
+                })
+                .collect::<Result<Vec<LogMessage>, _>>()?;
+
+            return Ok(vec![from_log(None, messages, names)]);
+        }
+    };
+
+    let is_chatgpt_export = match &value {
+        Value::Array(items) => items.iter().any(|item| item.get("mapping").is_some()),
+        _ => false,
+    };
+    if is_chatgpt_export {
+        let conversations: Vec<ChatGptConversation> =
+            serde_json::from_value(value).context("reading ChatGPT export")?;
+
+        return Ok(conversations
+            .into_iter()
+            .filter_map(|conversation| from_chatgpt(conversation, names))
+            .collect());
+    }
+
+    let logs: Vec<Log> = match value {
+        Value::Array(items) if items.iter().any(|item| item.get("messages").is_some()) => {
+            serde_json::from_value(Value::Array(items))
+        }
+        Value::Array(messages) => serde_json::from_value(Value::Array(messages)).map(|messages| {
+            vec![Log {
+                prompt: None,
+                messages,
+            }]
+        }),
+        // JSON lines with only the one line
+        Value::Object(message) if !message.contains_key("messages") => {
+            serde_json::from_value(Value::Object(message)).map(|message| {
+                vec![Log {
+                    prompt: None,
+                    messages: vec![message],
+                }]
+            })
+        }
+        log => serde_json::from_value(log).map(|log| vec![log]),
+    }
+    .context("reading JSON log")?;
+
+    Ok(logs
+        .into_iter()
+        .map(|log| from_log(log.prompt, log.messages, names))
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+struct Log {
+    #[serde(default)]
+    prompt: Option<String>,
+    messages: Vec<LogMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogMessage {
+    #[serde(alias = "role")]
+    sender: String,
+    content: String,
+    #[serde(default, alias = "created_at")]
+    timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+fn from_log(
+    mut prompt: Option<String>,
+    log: Vec<LogMessage>,
+    names: &Names,
+) -> ImportedConversation {
+    // Logs don't have IDs of their own, so they're recognized by what's in them. Timestamps are
+    // part of that when there are any, since the same words could easily be said twice.
+    let mut fingerprint = Fingerprint::default();
+    let imported_at = Utc::now();
+    let mut messages = Vec::new();
+    for message in log {
+        fingerprint.add(&message.sender);
+        fingerprint.add(&message.content);
+        if let Some(timestamp) = message.timestamp {
+            fingerprint.add(&timestamp.to_rfc3339());
+        }
+
+        let Some(sender) = sender_for_role(&message.sender, names) else {
+            prompt.get_or_insert(message.content);
+            continue;
+        };
+        messages.push(Message {
+            id: messages.len() as u64,
+            sender,
+            content: message.content,
+            timestamp: message.timestamp.unwrap_or(imported_at),
+            usage: message.usage,
+            alternates: None,
+            parent: None,
+            siblings: None,
+            sampling: None,
+            truncated: false,
+        });
+    }
+
+    ImportedConversation {
+        external_id: format!("log:{:016x}", fingerprint.0),
+        prompt,
+        messages,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptConversation {
+    id: String,
+    create_time: f64,
+    mapping: HashMap<String, ChatGptNode>,
+    current_node: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptNode {
+    message: Option<ChatGptMessage>,
+    parent: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    content: ChatGptContent,
+    create_time: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatGptContent {
+    // Text is in strings, and things like images are in objects
+    #[serde(default)]
+    parts: Vec<Value>,
+}
+
+// ChatGPT saves every version of a conversation as a tree, since responses can be regenerated and
+// messages edited. Only the branch that was last looked at is imported, which is found by walking
+// up from its last message. Conversations with nothing to import are skipped.
+fn from_chatgpt(conversation: ChatGptConversation, names: &Names) -> Option<ImportedConversation> {
+    let mut branch = Vec::new();
+    let mut node_id = Some(&conversation.current_node);
+    while let Some(node) = node_id.and_then(|id| conversation.mapping.get(id)) {
+        branch.extend(&node.message);
+        node_id = node.parent.as_ref();
+    }
+
+    let started_at = from_unix_time(conversation.create_time).unwrap_or_else(Utc::now);
+    let mut prompt = None;
+    let mut messages = Vec::new();
+    for message in branch.into_iter().rev() {
+        let content = message
+            .content
+            .parts
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        // Tool calls and their results aren't part of the conversation as far as we're concerned
+        if content.trim().is_empty() || message.author.role == "tool" {
+            continue;
+        }
+
+        let Some(sender) = sender_for_role(&message.author.role, names) else {
+            prompt.get_or_insert(content);
+            continue;
+        };
+        messages.push(Message {
+            id: messages.len() as u64,
+            sender,
+            content,
+            timestamp: message
+                .create_time
+                .and_then(from_unix_time)
+                .unwrap_or(started_at),
+            usage: None,
+            alternates: None,
+            parent: None,
+            siblings: None,
+            sampling: None,
+            truncated: false,
+        });
+    }
+
+    if messages.is_empty() {
+        return None;
+    }
+
+    Some(ImportedConversation {
+        external_id: format!("chatgpt:{}", conversation.id),
+        prompt,
+        messages,
+    })
+}
+
+// `None` for system messages, which are the conversation's prompt rather than part of it. Senders
+// that aren't a role are assumed to be a name already.
+fn sender_for_role(role: &str, names: &Names) -> Option<String> {
+    match role.to_lowercase().as_str() {
+        "system" => None,
+        "user" | "human" => Some(names.user.to_owned()),
+        "assistant" | "bot" | "ai" => Some(names.bot.to_owned()),
+        _ => Some(role.to_owned()),
+    }
+}
+
+fn from_unix_time(seconds: f64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt((seconds * 1000.0) as i64).single()
+}
+
+// FNV-1a, because it's tiny and gives the same hash every time the app runs, unlike the standard
+// library's hasher.
+struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fingerprint {
+    fn add(&mut self, field: &str) {
+        // A separator, so that moving text from one field to the next changes the fingerprint
+        for byte in field.bytes().chain([0]) {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        export::{self, ExportFormat},
+        message::{test_message, ConversationSummary},
+    };
+    use pretty_assertions::assert_eq;
+
+    const NAMES: Names = Names {
+        user: "User",
+        bot: "Bot",
+    };
+
+    fn senders_and_contents(conversation: &ImportedConversation) -> Vec<(&str, &str)> {
+        conversation
+            .messages
+            .iter()
+            .map(|m| (m.sender.as_str(), m.content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_chatgpt_export_imports_current_branch() {
+        // The first response was regenerated, and the second try is the one that was kept
+        let export = r#"[{
+            "id": "abc-123",
+            "title": "Greetings",
+            "create_time": 1680460200.5,
+            "current_node": "retry",
+            "mapping": {
+                "root": { "message": null, "parent": null },
+                "system": {
+                    "message": {
+                        "author": { "role": "system" },
+                        "content": { "content_type": "text", "parts": ["Be nice."] },
+                        "create_time": null
+                    },
+                    "parent": "root"
+                },
+                "hello": {
+                    "message": {
+                        "author": { "role": "user" },
+                        "content": { "content_type": "text", "parts": ["Hello!"] },
+                        "create_time": 1680460201.0
+                    },
+                    "parent": "system"
+                },
+                "first-try": {
+                    "message": {
+                        "author": { "role": "assistant" },
+                        "content": { "content_type": "text", "parts": ["Go away."] },
+                        "create_time": 1680460203.0
+                    },
+                    "parent": "hello"
+                },
+                "retry": {
+                    "message": {
+                        "author": { "role": "assistant" },
+                        "content": { "content_type": "text", "parts": ["Hi there!"] },
+                        "create_time": 1680460205.0
+                    },
+                    "parent": "hello"
+                }
+            }
+        }]"#;
+
+        let conversations = parse(export, &NAMES).unwrap();
+        assert_eq!(conversations.len(), 1);
+        let conversation = &conversations[0];
+        assert_eq!(conversation.external_id, "chatgpt:abc-123");
+        assert_eq!(conversation.prompt.as_deref(), Some("Be nice."));
+        assert_eq!(
+            senders_and_contents(conversation),
+            vec![("User", "Hello!"), ("Bot", "Hi there!")]
+        );
+        assert_eq!(
+            conversation.messages[1].timestamp,
+            Utc.timestamp_opt(1680460205, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_json_lines_with_one_message() {
+        let line = r#"{"id":1,"sender":"User","content":"Hi","timestamp":"2023-04-02T18:30:00Z"}"#;
+
+        let conversations = parse(line, &NAMES).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].prompt, None);
+        assert_eq!(
+            senders_and_contents(&conversations[0]),
+            vec![("User", "Hi")]
+        );
+    }
+
+    #[test]
+    fn test_exported_json_is_imported_as_it_was() {
+        let created_at = Utc.with_ymd_and_hms(2023, 4, 2, 18, 30, 0).unwrap();
+        let messages = vec![
+            Message {
+                timestamp: created_at,
+                ..test_message(1, "User", "Is 1 < 2?")
+            },
+            Message {
+                timestamp: created_at + chrono::Duration::seconds(3),
+                ..test_message(2, "Bot", "Yes.")
+            },
+        ];
+        let summary = ConversationSummary {
+            id: 7,
+            created_at,
+            prompt: "Be brief.".to_owned(),
+            message_count: messages.len(),
+            first_message: Some(messages[0].content.clone()),
+        };
+        let exported = export::render(ExportFormat::Json, &summary, &messages).unwrap();
+
+        let conversations = parse(&exported, &NAMES).unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].prompt.as_deref(), Some("Be brief."));
+        assert_eq!(
+            senders_and_contents(&conversations[0]),
+            vec![("User", "Is 1 < 2?"), ("Bot", "Yes.")]
+        );
+        assert_eq!(
+            conversations[0]
+                .messages
+                .iter()
+                .map(|m| m.timestamp)
+                .collect::<Vec<_>>(),
+            messages.iter().map(|m| m.timestamp).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_json_logs_are_recognized_by_their_contents() {
+        let lines = r#"{"id":1,"sender":"User","content":"Hi","timestamp":"2023-04-02T18:30:00Z","usage":null}
+{"id":2,"sender":"Bot","content":"Hello","timestamp":"2023-04-02T18:30:03Z","usage":{"prompt_tokens":10,"completion_tokens":1}}
+"#;
+        let array = r#"[
+            {"role": "user", "content": "Hi", "timestamp": "2023-04-02T18:30:00Z"},
+            {"role": "assistant", "content": "Hello", "timestamp": "2023-04-02T18:30:03Z"}
+        ]"#;
+
+        let from_lines = parse(lines, &NAMES).unwrap();
+        let from_array = parse(array, &NAMES).unwrap();
+        assert_eq!(
+            senders_and_contents(&from_lines[0]),
+            senders_and_contents(&from_array[0])
+        );
+        assert_eq!(
+            from_lines[0].messages[1]
+                .usage
+                .map(|usage| usage.total_tokens()),
+            Some(11)
+        );
+
+        // Roles are fingerprinted as they're written, so these are different logs
+        assert_ne!(from_lines[0].external_id, from_array[0].external_id);
+        assert_eq!(
+            parse(array, &NAMES).unwrap()[0].external_id,
+            from_array[0].external_id
+        );
+    }
+}
@@ -1,15 +1,22 @@
 pub mod app;
 pub mod args;
+mod db;
+pub mod export;
+pub mod import;
 pub mod message;
 pub mod openai_api;
+pub mod provider;
+pub mod sampling;
+mod tokenizer;
 
 use app::App;
-use args::Args;
+use args::{Args, Command};
 use tracing_subscriber::filter::EnvFilter;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    dotenv::dotenv().expect("failed to read .env file, please create one");
+    // The .env file is optional since OpenAI's settings aren't needed by every provider
+    let _ = dotenv::dotenv();
     let log_file = std::fs::File::create("debug.log")?;
     let (non_blocking, _guard) = tracing_appender::non_blocking(log_file);
     tracing_subscriber::fmt()
@@ -20,5 +27,15 @@ async fn main() -> anyhow::Result<()> {
 
     let args = Args::parse();
 
-    App::run_until_exit(args).await
+    match args.command() {
+        Some(Command::Search { query, limit }) => App::search(&args, &query.join(" "), *limit),
+        Some(Command::Export {
+            conversation_id,
+            all: _,
+            format,
+            output,
+        }) => App::export(&args, *conversation_id, *format, output.as_deref()),
+        Some(Command::Import { files }) => App::import(&args, files),
+        None => App::run_until_exit(args).await,
+    }
 }
@@ -1,12 +1,182 @@
+pub mod branch;
+
+use crate::sampling::SamplingParameters;
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Message {
     pub id: u64,
     pub sender: String,
     pub content: String,
     pub timestamp: DateTime<Utc>,
+    /// How many tokens it took to get this message from the bot. Only set for bot messages, and
+    /// only when the provider reports it.
+    pub usage: Option<TokenUsage>,
+    /// The sampling parameters the bot was asked for this message with. Only set for bot messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingParameters>,
+    /// The bot ran into the token limit before it finished this message, so there's more to say.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Every response the bot has come up with for this turn, once there's been more than one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alternates: Option<Alternates>,
+    /// The ID of the message this one follows, or `None` if it's the first.
+    pub parent: Option<u64>,
+    /// Where this message is among the other versions of it, when an earlier message was edited.
+    /// This isn't saved; it's worked out from the rest of the conversation when it's loaded.
+    #[serde(skip)]
+    pub siblings: Option<Siblings>,
+}
+
+impl Message {
+    /// Add another response (or several) to choose between for this turn, and choose it. The
+    /// tokens it took are added to this message's usage.
+    pub fn add_alternates(&mut self, response: Message) {
+        let mut responses = match self.alternates.take() {
+            Some(alternates) => alternates.responses,
+            None => vec![Alternate::from(&*self)],
+        };
+        let chosen = responses.len();
+        match response.alternates {
+            Some(alternates) => responses.extend(alternates.responses),
+            None => responses.push(Alternate::from(&response)),
+        }
+
+        self.usage = match (self.usage, response.usage) {
+            (Some(usage), Some(more)) => Some(usage + more),
+            (usage, more) => usage.or(more),
+        };
+        self.alternates = Some(Alternates { responses, chosen });
+        self.show_chosen_alternate();
+    }
+
+    /// Choose the next of the alternates, or the previous one when `forward` is false, wrapping
+    /// around at the ends. Returns whether there was anything to choose between.
+    pub fn cycle_alternates(&mut self, forward: bool) -> bool {
+        let Some(alternates) = &mut self.alternates else {
+            return false;
+        };
+
+        let count = alternates.responses.len();
+        alternates.chosen = if forward {
+            (alternates.chosen + 1) % count
+        } else {
+            (alternates.chosen + count - 1) % count
+        };
+        self.show_chosen_alternate();
+
+        true
+    }
+
+    /// Note the sampling parameters a response from the bot was asked for with, along with any
+    /// alternates that came with it.
+    pub fn record_sampling(&mut self, sampling: &SamplingParameters) {
+        self.sampling = Some(sampling.clone());
+        if let Some(alternates) = &mut self.alternates {
+            for alternate in &mut alternates.responses {
+                alternate.sampling = Some(sampling.clone());
+            }
+        }
+    }
+
+    /// Add the rest of a response the bot was cut off partway through writing. Whether it's still
+    /// cut off is up to the rest.
+    pub fn continue_with(&mut self, rest: Message) {
+        self.content.push_str(&rest.content);
+        self.truncated = rest.truncated;
+        self.usage = match (self.usage, rest.usage) {
+            (Some(usage), Some(more)) => Some(usage + more),
+            (usage, more) => usage.or(more),
+        };
+        if let Some(alternates) = &mut self.alternates {
+            let chosen = &mut alternates.responses[alternates.chosen];
+            chosen.content = self.content.clone();
+            chosen.truncated = self.truncated;
+        }
+    }
+
+    fn show_chosen_alternate(&mut self) {
+        if let Some(alternates) = &self.alternates {
+            let chosen = &alternates.responses[alternates.chosen];
+            self.content = chosen.content.clone();
+            self.timestamp = chosen.timestamp;
+            self.sampling = chosen.sampling.clone();
+            self.truncated = chosen.truncated;
+        }
+    }
+}
+
+/// A message's place among the messages that follow the same message as it, oldest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Siblings {
+    pub position: usize,
+    pub count: usize,
+}
+
+/// The responses to choose between for a bot message that's been regenerated.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alternates {
+    /// Oldest first. This is never empty.
+    pub responses: Vec<Alternate>,
+    /// The index of the response that's the message's content
+    pub chosen: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alternate {
+    /// The ID it was saved to the database with, or 0 if it hasn't been saved yet.
+    pub id: u64,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingParameters>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl From<&Message> for Alternate {
+    fn from(message: &Message) -> Self {
+        Self {
+            id: 0,
+            content: message.content.clone(),
+            timestamp: message.timestamp,
+            sampling: message.sampling.clone(),
+            truncated: message.truncated,
+        }
+    }
+}
+
+/// Token counts as reported by OpenAI, which is what they bill by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u32 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl std::ops::Add for TokenUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+        }
+    }
+}
+
+impl std::iter::Sum for TokenUsage {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, usage| total + usage)
+    }
 }
 
 impl PartialEq for Message {
@@ -24,3 +194,109 @@ impl PartialOrd for Message {
         self.timestamp.partial_cmp(&other.timestamp)
     }
 }
+
+/// What's shown about a saved conversation when choosing one to open.
+#[derive(Debug, Clone)]
+pub struct ConversationSummary {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub prompt: String,
+    pub message_count: usize,
+    pub first_message: Option<String>,
+}
+
+/// A message that matched a search, with the matching words highlighted in a snippet of it.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub conversation_id: i64,
+    pub message_id: u64,
+    pub sender: String,
+    pub timestamp: DateTime<Utc>,
+    /// Each match is wrapped in [`SearchHit::MATCH_START`] and [`SearchHit::MATCH_END`].
+    pub snippet: String,
+}
+
+impl SearchHit {
+    // Control characters, since anything printable could be part of the message itself
+    pub const MATCH_START: char = '\u{2}';
+    pub const MATCH_END: char = '\u{3}';
+
+    /// The snippet without the markers around the matches, on one line.
+    pub fn plain_snippet(&self) -> String {
+        self.snippet
+            .replace([Self::MATCH_START, Self::MATCH_END], "")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// A message with only the basics filled in, following the message before it.
+#[cfg(test)]
+pub fn test_message(id: u64, sender: &str, content: &str) -> Message {
+    Message {
+        id,
+        sender: sender.to_owned(),
+        content: content.to_owned(),
+        timestamp: Utc::now(),
+        usage: None,
+        sampling: None,
+        truncated: false,
+        alternates: None,
+        parent: id.checked_sub(1).filter(|parent| *parent > 0),
+        siblings: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn bot_message(content: &str, completion_tokens: u32) -> Message {
+        Message {
+            usage: Some(TokenUsage {
+                prompt_tokens: 10,
+                completion_tokens,
+            }),
+            ..test_message(2, "Bot", content)
+        }
+    }
+
+    #[test]
+    fn test_regenerated_responses_are_kept_as_alternates() {
+        let mut message = bot_message("Go away.", 2);
+        assert!(!message.cycle_alternates(true));
+
+        message.add_alternates(bot_message("Hi there!", 2));
+        message.add_alternates(bot_message("Hello!", 1));
+        assert_eq!(message.content, "Hello!");
+        assert_eq!(message.alternates.as_ref().map(|a| a.chosen), Some(2));
+        assert_eq!(message.usage.map(|usage| usage.total_tokens()), Some(35));
+
+        // Going forward from the newest wraps around to the oldest
+        assert!(message.cycle_alternates(true));
+        assert_eq!(message.content, "Go away.");
+        message.cycle_alternates(false);
+        message.cycle_alternates(false);
+        assert_eq!(message.content, "Hi there!");
+    }
+
+    #[test]
+    fn test_continuing_a_cut_off_response_adds_to_the_chosen_alternate() {
+        let mut message = bot_message("Go away.", 2);
+        message.add_alternates(Message {
+            truncated: true,
+            ..bot_message("Once upon a", 3)
+        });
+        message.continue_with(bot_message(" time.", 2));
+        assert_eq!(message.content, "Once upon a time.");
+        assert!(!message.truncated);
+        assert_eq!(message.usage.map(|usage| usage.total_tokens()), Some(37));
+
+        // Switching away and back shows the whole thing
+        message.cycle_alternates(true);
+        message.cycle_alternates(true);
+        assert_eq!(message.content, "Once upon a time.");
+    }
+}
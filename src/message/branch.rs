@@ -0,0 +1,192 @@
+//! Conversations are trees. Every message points at the one it follows, and editing an earlier
+//! message saves the edit alongside the original, following the same message, instead of
+//! replacing it. The conversation carries on from the edit, and the original's branch is still
+//! there to switch back to.
+//!
+//! Only one branch is shown at a time. Unless the user picks another one, that's the branch that
+//! was added to most recently.
+
+use super::{Message, Siblings};
+use std::collections::{HashMap, HashSet};
+
+/// The branch ending with the message `leaf`, walked from the root of the conversation down to it.
+/// Each message is marked with where it is among its siblings.
+pub fn branch_to(messages: &[Message], leaf: u64) -> Vec<Message> {
+    let by_id: HashMap<u64, &Message> = messages.iter().map(|m| (m.id, m)).collect();
+    let mut branch = Vec::new();
+    let mut next = by_id.get(&leaf);
+    while let Some(message) = next {
+        branch.push(Message {
+            siblings: siblings_of(messages, message),
+            ..(*message).clone()
+        });
+        next = message.parent.and_then(|parent| by_id.get(&parent));
+    }
+    branch.reverse();
+
+    branch
+}
+
+/// The branch that was added to most recently.
+pub fn newest_branch(messages: &[Message]) -> Vec<Message> {
+    match messages.iter().map(|m| m.id).max() {
+        Some(leaf) => branch_to(messages, leaf),
+        None => Vec::new(),
+    }
+}
+
+/// The branch that the message `id` is on. Below it, the branch that was added to most recently is
+/// followed. If there's no such message, it's the newest branch.
+pub fn branch_through(messages: &[Message], id: u64) -> Vec<Message> {
+    if messages.iter().any(|m| m.id == id) {
+        branch_to(messages, newest_descendant(messages, id))
+    } else {
+        newest_branch(messages)
+    }
+}
+
+/// The branch through the sibling after the message `id`, or the one before it when `forward` is
+/// false, wrapping around at the ends. Below the sibling, the branch that was added to most
+/// recently is followed. Returns `None` when the message doesn't have any siblings.
+pub fn switch_branch(messages: &[Message], id: u64, forward: bool) -> Option<Vec<Message>> {
+    let message = messages.iter().find(|m| m.id == id)?;
+    let siblings = sibling_ids(messages, message);
+    if siblings.len() < 2 {
+        return None;
+    }
+
+    let position = siblings.iter().position(|sibling| *sibling == id)?;
+    let sibling = if forward {
+        siblings[(position + 1) % siblings.len()]
+    } else {
+        siblings[(position + siblings.len() - 1) % siblings.len()]
+    };
+
+    Some(branch_through(messages, sibling))
+}
+
+// Messages are saved after the message they follow, so a message's descendants all come after it.
+fn newest_descendant(messages: &[Message], id: u64) -> u64 {
+    let mut descendants = HashSet::from([id]);
+    let mut newest = id;
+    for message in messages.iter().filter(|m| m.id > id) {
+        if message
+            .parent
+            .is_some_and(|parent| descendants.contains(&parent))
+        {
+            descendants.insert(message.id);
+            newest = newest.max(message.id);
+        }
+    }
+
+    newest
+}
+
+// Every message following the same message as `message`, including itself, oldest first
+fn sibling_ids(messages: &[Message], message: &Message) -> Vec<u64> {
+    messages
+        .iter()
+        .filter(|m| m.parent == message.parent)
+        .map(|m| m.id)
+        .collect()
+}
+
+fn siblings_of(messages: &[Message], message: &Message) -> Option<Siblings> {
+    let siblings = sibling_ids(messages, message);
+
+    (siblings.len() > 1).then(|| Siblings {
+        position: siblings
+            .iter()
+            .position(|id| *id == message.id)
+            .unwrap_or(0),
+        count: siblings.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::test_message;
+    use pretty_assertions::assert_eq;
+
+    fn message(id: u64, parent: Option<u64>, content: &str) -> Message {
+        let sender = if id % 2 == 1 { "User" } else { "Bot" };
+        Message {
+            parent,
+            ..test_message(id, sender, content)
+        }
+    }
+
+    fn contents(branch: &[Message]) -> Vec<&str> {
+        branch.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    // The second message from the user was edited, and then the first one was too
+    fn edited_conversation() -> Vec<Message> {
+        vec![
+            message(1, None, "Hi"),
+            message(2, Some(1), "Hello!"),
+            message(3, Some(2), "What's 2 + 2?"),
+            message(4, Some(3), "5"),
+            message(5, Some(2), "What's 2 + 2? Think carefully."),
+            message(6, Some(5), "4"),
+            message(7, None, "Hey"),
+            message(8, Some(7), "Hi there!"),
+        ]
+    }
+
+    #[test]
+    fn test_newest_branch_is_shown() {
+        let messages = edited_conversation();
+
+        let branch = newest_branch(&messages);
+        assert_eq!(contents(&branch), vec!["Hey", "Hi there!"]);
+        assert_eq!(
+            branch[0].siblings,
+            Some(Siblings {
+                position: 1,
+                count: 2
+            })
+        );
+        assert_eq!(branch[1].siblings, None);
+        assert!(newest_branch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_switching_branches_follows_the_newest_one_down() {
+        let messages = edited_conversation();
+
+        let branch = switch_branch(&messages, 7, true).unwrap();
+        assert_eq!(
+            contents(&branch),
+            vec!["Hi", "Hello!", "What's 2 + 2? Think carefully.", "4"]
+        );
+
+        let branch = switch_branch(&messages, 5, false).unwrap();
+        assert_eq!(
+            contents(&branch),
+            vec!["Hi", "Hello!", "What's 2 + 2?", "5"]
+        );
+        assert_eq!(branch[2].siblings.map(|s| s.position), Some(0));
+
+        assert!(switch_branch(&messages, 8, true).is_none());
+    }
+
+    #[test]
+    fn test_branch_through_an_older_message() {
+        let messages = edited_conversation();
+
+        assert_eq!(
+            contents(&branch_through(&messages, 3)),
+            vec!["Hi", "Hello!", "What's 2 + 2?", "5"]
+        );
+        assert_eq!(
+            contents(&branch_through(&messages, 2)),
+            vec!["Hi", "Hello!", "What's 2 + 2? Think carefully.", "4"]
+        );
+        assert_eq!(
+            contents(&branch_through(&messages, 99)),
+            vec!["Hey", "Hi there!"]
+        );
+    }
+}
@@ -1,62 +1,464 @@
+mod chat_completion;
+mod error;
+mod postprocess;
+pub mod pricing;
+mod prompt;
+mod retry;
+mod sse;
 mod text_completion;
 
-use crate::{message::Message, openai_api::text_completion::TextCompletionResponse};
-use once_cell::sync::Lazy;
-use text_completion::TextCompletionRequest;
-use tracing::{debug, instrument};
+use crate::{
+    message::{Alternate, Alternates, Message, TokenUsage},
+    openai_api::text_completion::TextCompletionResponse,
+    provider::{Provider, ResponseProgress, ResponseRequest},
+    sampling::SamplingParameters,
+};
+use chat_completion::{ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse};
+use futures::future::{BoxFuture, FutureExt};
+use prompt::{create_chat_messages_from_messages, create_prompt_from_messages};
+use serde::{de::DeserializeOwned, Serialize};
+use sse::SseParser;
+use std::time::Duration;
+use text_completion::{TextCompletionChunk, TextCompletionRequest};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, instrument, trace, warn};
 
-const COMPLETIONS_URI: &str = "https://api.openai.com/v1/completions";
-static OPENAI_API_KEY: Lazy<String> = Lazy::new(|| std::env::var("OPENAI_API_KEY").unwrap());
-static OPENAI_ORGANIZATION_ID: Lazy<String> =
-    Lazy::new(|| std::env::var("OPENAI_ORGANIZATION_ID").unwrap());
+pub use error::ApiError;
+pub use retry::RetryPolicy;
 
-// TODO this is fallible and should return a result
-#[instrument]
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+// Requests that hang are given up on so that they can be retried like any other timeout. Streamed
+// responses can take a while to finish, so whole requests get a lot longer than connecting does.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Where to send requests and how to authenticate them.
+///
+/// Anything that speaks OpenAI's protocol can be used by changing the base URL. Servers running
+/// locally often don't need an API key or an organization, so both are optional.
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    organization_id: Option<String>,
+}
+
+impl ApiConfig {
+    pub fn new(
+        base_url: impl Into<String>,
+        api_key: Option<String>,
+        organization_id: Option<String>,
+    ) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            api_key,
+            organization_id,
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let mut req = self
+            .client
+            .request(method, format!("{}/{path}", self.base_url));
+        if let Some(api_key) = &self.api_key {
+            req = req.bearer_auth(api_key);
+        }
+        if let Some(organization_id) = &self.organization_id {
+            req = req.header("OpenAI-Organization", organization_id);
+        }
+
+        req
+    }
+}
+
+/// Extra options for streamed requests.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StreamOptions {
+    /// Send one last chunk with the token usage of the whole request before the stream ends.
+    pub include_usage: bool,
+}
+
+/// One of the responses the API came back with, before it's made into a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RawResponse {
+    text: String,
+    /// The response ran into `max_tokens` before the bot was done.
+    truncated: bool,
+}
+
+impl RawResponse {
+    fn new(text: impl Into<String>, finish_reason: Option<&str>) -> Self {
+        Self {
+            text: text.into(),
+            truncated: finish_reason == Some("length"),
+        }
+    }
+}
+
+/// OpenAI serves its models from two different endpoints. Older models like "text-davinci-003" only
+/// accept a flat string prompt while the chat models only accept a list of role-tagged messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Completions,
+    ChatCompletions,
+}
+
+impl Endpoint {
+    pub fn for_model(model: &str) -> Self {
+        if model.starts_with("gpt-3.5-turbo") || model.starts_with("gpt-4") {
+            Self::ChatCompletions
+        } else {
+            Self::Completions
+        }
+    }
+}
+
+/// Fetches responses from OpenAI, picking the endpoint that serves the requested model.
+#[derive(Debug)]
+pub struct OpenAiProvider {
+    config: ApiConfig,
+    retry_policy: RetryPolicy,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: ApiConfig, retry_policy: RetryPolicy) -> Self {
+        Self {
+            config,
+            retry_policy,
+        }
+    }
+
+    pub async fn list_models(&self) -> Result<(), ApiError> {
+        let res = send(self.config.request(reqwest::Method::GET, "models")).await?;
+
+        debug!("{:#?}", res);
+        let body_str = res.text().await?;
+        debug!("{body_str}");
+
+        Ok(())
+    }
+}
+
+impl Provider for OpenAiProvider {
+    fn fetch_response(
+        &self,
+        request: ResponseRequest,
+        progress_tx: UnboundedSender<ResponseProgress>,
+    ) -> BoxFuture<'static, Result<Message, anyhow::Error>> {
+        let config = self.config.clone();
+        let retry_policy = self.retry_policy;
+
+        async move {
+            let body = match Endpoint::for_model(&request.model) {
+                Endpoint::Completions => {
+                    let prompt = create_prompt_from_messages(
+                        &request.starting_prompt,
+                        &request.history,
+                        request.continuing,
+                    );
+                    let sampling = SamplingParameters {
+                        stop: postprocess::stop_sequences(
+                            &request.sampling.stop,
+                            &request.your_name,
+                        ),
+                        ..request.sampling.clone()
+                    };
+                    RequestBody::Text(
+                        TextCompletionRequest::builder()
+                            .prompt(prompt)
+                            .model(request.model)
+                            .max_tokens(request.max_tokens)
+                            .stream(request.stream)
+                            .n(request.n)
+                            .sampling(sampling)
+                            .build()?,
+                    )
+                }
+                Endpoint::ChatCompletions => {
+                    let messages = create_chat_messages_from_messages(
+                        &request.starting_prompt,
+                        &request.your_name,
+                        &request.history,
+                        request.continuing,
+                    );
+                    RequestBody::Chat(
+                        ChatCompletionRequest::builder()
+                            .messages(messages)
+                            .model(request.model)
+                            .max_tokens(request.max_tokens)
+                            .stream(request.stream)
+                            .n(request.n)
+                            .sampling(request.sampling.clone())
+                            .build()?,
+                    )
+                }
+            };
+
+            let mut attempt = 1;
+            loop {
+                let result = match &body {
+                    RequestBody::Text(body) => {
+                        fetch_response_to_prompt(
+                            &config,
+                            request.id,
+                            &request.your_name,
+                            &request.their_name,
+                            request.continuing,
+                            body,
+                            &progress_tx,
+                        )
+                        .await
+                    }
+                    RequestBody::Chat(body) => {
+                        fetch_response_to_chat(
+                            &config,
+                            request.id,
+                            &request.their_name,
+                            request.continuing,
+                            body,
+                            &progress_tx,
+                        )
+                        .await
+                    }
+                };
+
+                match result {
+                    Ok(message) => return Ok(message),
+                    Err(e) => match retry_policy.delay_before_retry(attempt, &e) {
+                        Some(delay) => {
+                            attempt += 1;
+                            warn!("{e}, retrying in {delay:?} (attempt {attempt})");
+                            let _ = progress_tx.send(ResponseProgress::Retrying {
+                                attempt,
+                                max_attempts: retry_policy.max_attempts(),
+                            });
+                            tokio::time::sleep(delay).await;
+                        }
+                        None => return Err(e.into()),
+                    },
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+enum RequestBody {
+    Text(TextCompletionRequest),
+    Chat(ChatCompletionRequest),
+}
+
+#[instrument(skip(config, progress_tx))]
 pub async fn fetch_response_to_prompt(
+    config: &ApiConfig,
     id: u64,
-    prompt: String,
-    their_name: String,
-    model: String,
-    max_tokens: u32,
-) -> Result<Message, anyhow::Error> {
-    let client = reqwest::Client::new();
-    let body = TextCompletionRequest::builder()
-        .prompt(prompt.to_owned())
-        .model(model.to_owned())
-        .max_tokens(max_tokens)
-        .build()?;
+    your_name: &str,
+    their_name: &str,
+    continuing: bool,
+    body: &TextCompletionRequest,
+    progress_tx: &UnboundedSender<ResponseProgress>,
+) -> Result<Message, ApiError> {
+    let stream = body.stream.unwrap_or_default();
 
     debug!(?body, "sending request to OpenAI Completions API...");
-    let res = client
-        .post(COMPLETIONS_URI)
-        .bearer_auth(OPENAI_API_KEY.as_str())
-        .header("Content-Type", "application/json")
-        .header("OpenAI-Organization", OPENAI_ORGANIZATION_ID.as_str())
-        .json(&body)
-        .send()
-        .await
-        // TODO gracefully handle errors
-        .expect("request is valid");
+    let res = send(
+        config
+            .request(reqwest::Method::POST, "completions")
+            .header("Content-Type", "application/json")
+            .json(body),
+    )
+    .await?;
 
     debug!(
         response = ?res,
         "received response from OpenAI Completions API"
     );
 
-    let body: TextCompletionResponse = res.json().await.unwrap();
-    // Sometimes the bot will prefix responses with it's name. We want to remove that since we
-    // handle that in the UI.
-    // TODO don't unwrap here
-    let content = body
-        .message()
-        .trim_start_matches(&format!("{their_name}:"))
-        .to_owned();
+    let (responses, usage) = if stream {
+        let (response, usage) =
+            read_event_stream(res, progress_tx, |chunk: TextCompletionChunk| {
+                (
+                    chunk.delta().map(ToOwned::to_owned),
+                    chunk.finish_reason().map(ToOwned::to_owned),
+                    chunk.usage(),
+                )
+            })
+            .await?;
+        (vec![response], usage)
+    } else {
+        let body: TextCompletionResponse = serde_json::from_slice(&res.bytes().await?)?;
+        (body.responses(), body.usage())
+    };
+    let responses = responses
+        .into_iter()
+        .map(|response| {
+            let text = postprocess::cut_hallucinated_turns(&response.text, your_name);
+            // Whatever was cut off was past the end of the bot's turn, so the turn was finished
+            RawResponse {
+                truncated: response.truncated && text.len() == response.text.len(),
+                text: text.to_owned(),
+            }
+        })
+        .collect();
+
+    message_from_responses(id, their_name.to_owned(), responses, usage, continuing)
+}
+
+#[instrument(skip(config, progress_tx))]
+pub async fn fetch_response_to_chat(
+    config: &ApiConfig,
+    id: u64,
+    their_name: &str,
+    continuing: bool,
+    body: &ChatCompletionRequest,
+    progress_tx: &UnboundedSender<ResponseProgress>,
+) -> Result<Message, ApiError> {
+    let stream = body.stream.unwrap_or_default();
+
+    debug!(?body, "sending request to OpenAI Chat Completions API...");
+    let res = send(
+        config
+            .request(reqwest::Method::POST, "chat/completions")
+            .header("Content-Type", "application/json")
+            .json(body),
+    )
+    .await?;
+
+    debug!(
+        response = ?res,
+        "received response from OpenAI Chat Completions API"
+    );
+
+    let (responses, usage) = if stream {
+        let (response, usage) =
+            read_event_stream(res, progress_tx, |chunk: ChatCompletionChunk| {
+                (
+                    chunk.delta().map(ToOwned::to_owned),
+                    chunk.finish_reason().map(ToOwned::to_owned),
+                    chunk.usage(),
+                )
+            })
+            .await?;
+        (vec![response], usage)
+    } else {
+        let body: ChatCompletionResponse = serde_json::from_slice(&res.bytes().await?)?;
+        (body.responses(), body.usage())
+    };
+
+    message_from_responses(id, their_name.to_owned(), responses, usage, continuing)
+}
+
+/// Send a request, turning any error status into an [`ApiError`].
+async fn send(req: reqwest::RequestBuilder) -> Result<reqwest::Response, ApiError> {
+    let res = req.send().await?;
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+
+    let retry_after = res
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64);
+    let body = res.text().await?;
+    debug!(%status, body, "OpenAI responded with an error");
+
+    Err(ApiError::from_response(status, retry_after, &body))
+}
+
+/// Read a streamed response to completion, passing each delta along to `progress_tx` as it
+/// arrives. Returns the whole response once the stream is done, along with its usage if the server
+/// sent it. `parts_of` picks the delta, finish reason, and usage out of each chunk.
+async fn read_event_stream<T: DeserializeOwned>(
+    mut res: reqwest::Response,
+    progress_tx: &UnboundedSender<ResponseProgress>,
+    parts_of: impl Fn(T) -> (Option<String>, Option<String>, Option<TokenUsage>),
+) -> Result<(RawResponse, Option<TokenUsage>), ApiError> {
+    let mut parser = SseParser::default();
+    let mut response = String::new();
+    let mut finish_reason = None;
+    let mut usage = None;
+
+    'stream: while let Some(bytes) = res.chunk().await? {
+        for data in parser.push(&bytes) {
+            if data == sse::DONE {
+                break 'stream;
+            }
+
+            let chunk: T = serde_json::from_str(&data)?;
+            let (delta, chunk_finish_reason, chunk_usage) = parts_of(chunk);
+            finish_reason = chunk_finish_reason.or(finish_reason);
+            usage = chunk_usage.or(usage);
+            if let Some(delta) = delta.filter(|delta| !delta.is_empty()) {
+                trace!(delta, "received delta from OpenAI");
+                response.push_str(&delta);
+                // The receiver going away just means nobody is watching the response come in
+                let _ = progress_tx.send(ResponseProgress::Delta(delta));
+            }
+        }
+    }
+
+    Ok((RawResponse::new(response, finish_reason.as_deref()), usage))
+}
+
+// The first response is the message's content. When more than one was asked for, they're all kept
+// as alternates. When `continuing`, the responses carry on from the end of the bot's last message,
+// so they're left as they start.
+fn message_from_responses(
+    id: u64,
+    their_name: String,
+    responses: Vec<RawResponse>,
+    usage: Option<TokenUsage>,
+    continuing: bool,
+) -> Result<Message, ApiError> {
+    let timestamp = chrono::Utc::now();
+    let alternates: Vec<Alternate> = responses
+        .iter()
+        .map(|response| {
+            let content = if continuing {
+                response.text.trim_end()
+            } else {
+                postprocess::strip_leading_name(&response.text, &their_name).trim_end()
+            };
+
+            Alternate {
+                id: 0,
+                content: content.to_owned(),
+                timestamp,
+                sampling: None,
+                truncated: response.truncated,
+            }
+        })
+        .collect();
+    let Some(first) = alternates.first().cloned() else {
+        return Err(ApiError::NoChoices);
+    };
 
     let message = Message {
         id,
         sender: their_name,
-        content,
-        timestamp: chrono::Utc::now(),
+        content: first.content,
+        timestamp,
+        usage,
+        alternates: (alternates.len() > 1).then_some(Alternates {
+            responses: alternates,
+            chosen: 0,
+        }),
+        parent: None,
+        siblings: None,
+        sampling: None,
+        truncated: first.truncated,
     };
 
     debug!(
@@ -69,20 +471,339 @@ pub async fn fetch_response_to_prompt(
     Ok(message)
 }
 
-pub async fn list_models() {
-    let client = reqwest::Client::new();
-    let res = client
-        .get("https://api.openai.com/v1/models")
-        .bearer_auth(OPENAI_API_KEY.as_str())
-        .header("OpenAI-Organization", OPENAI_ORGANIZATION_ID.as_str())
-        .send()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::test_message;
+    use chat_completion::{ChatMessage, Role};
+    use pretty_assertions::assert_eq;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread::{self, JoinHandle},
+    };
+    use tokio::sync::mpsc;
+
+    /// Serve a single request with a canned response. The request is returned when the thread is
+    /// joined so that tests can check what was sent.
+    fn serve_once(content_type: &'static str, body: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+            request.push_str(&String::from_utf8(request_body).unwrap());
+
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+
+            request
+        });
+
+        (base_url, handle)
+    }
+
+    #[tokio::test]
+    async fn test_listing_models_from_a_server_that_is_down_fails() {
+        // Nothing's listening on the port once the listener's gone
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        drop(listener);
+
+        let provider = OpenAiProvider::new(
+            ApiConfig::new(base_url, None, None).unwrap(),
+            RetryPolicy::new(1, Duration::ZERO),
+        );
+        assert!(matches!(
+            provider.list_models().await,
+            Err(ApiError::Http(e)) if e.is_connect()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_response_to_prompt_from_local_server() {
+        let (base_url, server) = serve_once(
+            "application/json",
+            r#"{
+                "choices": [{"text": "\n\ntest_bot: Hello user."}],
+                "usage": {"prompt_tokens": 9, "completion_tokens": 6, "total_tokens": 15}
+            }"#,
+        );
+        let (progress_tx, _progress_rx) = mpsc::unbounded_channel();
+
+        let message = fetch_response_to_prompt(
+            &ApiConfig::new(base_url, None, None).unwrap(),
+            1,
+            "test_user",
+            "test_bot",
+            false,
+            &TextCompletionRequest::builder()
+                .prompt("test_user:\nHello bot.\n\n")
+                .model("local-model")
+                .max_tokens(100)
+                .sampling(SamplingParameters {
+                    temperature: Some(0.7),
+                    stop: vec!["\ntest_user:".to_owned()],
+                    ..Default::default()
+                })
+                .build()
+                .unwrap(),
+            &progress_tx,
+        )
         .await
-        .expect("request is valid");
+        .unwrap();
+        assert_eq!(message.content, "Hello user.");
+        assert_eq!(
+            message.usage,
+            Some(TokenUsage {
+                prompt_tokens: 9,
+                completion_tokens: 6
+            })
+        );
 
-    debug!("{:#?}", res);
-    let res_body = res.bytes().await.expect("response body is valid");
-    let body_str = String::from_utf8(res_body.to_vec()).expect("response body is valid utf8");
-    debug!("{body_str}");
-}
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/completions HTTP/1.1"));
+        // Neither an API key nor an organization was configured, so neither should be sent
+        assert!(!request.to_lowercase().contains("authorization"));
+        assert!(!request.to_lowercase().contains("openai-organization"));
+        // Only the sampling parameters that were set are sent
+        assert!(request.contains(r#""temperature":0.7"#));
+        assert!(request.contains(r#""stop":["\ntest_user:"]"#));
+        assert!(!request.contains("top_p"));
+    }
+
+    #[tokio::test]
+    async fn test_cut_off_response_is_continued_where_it_stopped() {
+        let (base_url, server) = serve_once(
+            "application/json",
+            r#"{
+                "choices": [{"text": " and the sea is", "finish_reason": "length"}],
+                "usage": {"prompt_tokens": 20, "completion_tokens": 5}
+            }"#,
+        );
+        let (progress_tx, _progress_rx) = mpsc::unbounded_channel();
+        let history = vec![
+            test_message(1, "test_user", "Describe the view."),
+            Message {
+                truncated: true,
+                ..test_message(2, "test_bot", "The sky is blue")
+            },
+        ];
+        let prompt = create_prompt_from_messages("", &history, true);
+        assert_eq!(
+            prompt,
+            "test_user:\nDescribe the view.\n\ntest_bot:\nThe sky is blue"
+        );
+
+        let message = fetch_response_to_prompt(
+            &ApiConfig::new(base_url, None, None).unwrap(),
+            2,
+            "test_user",
+            "test_bot",
+            true,
+            &TextCompletionRequest::builder()
+                .prompt(prompt)
+                .model("local-model")
+                .max_tokens(5)
+                .build()
+                .unwrap(),
+            &progress_tx,
+        )
+        .await
+        .unwrap();
+        // It still isn't finished, and it starts where the last part left off
+        assert_eq!(message.content, " and the sea is");
+        assert!(message.truncated);
+
+        let request = server.join().unwrap();
+        assert!(request.contains(
+            r#""prompt":"test_user:\nDescribe the view.\n\ntest_bot:\nThe sky is blue""#
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_chat_models_are_asked_to_continue_cut_off_responses() {
+        let (base_url, server) = serve_once(
+            "application/json",
+            r#"{
+                "choices": [{"message": {"role": "assistant", "content": " and the sea is green."}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 40, "completion_tokens": 6}
+            }"#,
+        );
+        let (progress_tx, _progress_rx) = mpsc::unbounded_channel();
+        let provider = OpenAiProvider::new(
+            ApiConfig::new(base_url, None, None).unwrap(),
+            RetryPolicy::new(1, Duration::ZERO),
+        );
 
-// TODO test these APIs somehow
+        let message = provider
+            .fetch_response(
+                ResponseRequest {
+                    id: 2,
+                    starting_prompt: String::new(),
+                    your_name: "test_user".to_owned(),
+                    their_name: "test_bot".to_owned(),
+                    history: vec![
+                        test_message(1, "test_user", "Describe the view."),
+                        Message {
+                            truncated: true,
+                            ..test_message(2, "test_bot", "The sky is blue")
+                        },
+                    ],
+                    model: "gpt-3.5-turbo".to_owned(),
+                    max_tokens: 100,
+                    stream: false,
+                    n: 1,
+                    sampling: Default::default(),
+                    continuing: true,
+                },
+                progress_tx,
+            )
+            .await
+            .unwrap();
+        assert_eq!(message.content, " and the sea is green.");
+        assert!(!message.truncated);
+
+        // The cut-off response is sent as it is, followed by the instruction to carry on with it
+        let request = server.join().unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(messages[1]["content"], "The sky is blue");
+        assert_eq!(messages[2]["role"], "system");
+        assert!(messages[2]["content"]
+            .as_str()
+            .unwrap()
+            .contains("Continue it from exactly where it stopped"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_streamed_response_to_chat_from_local_server() {
+        let (base_url, server) = serve_once(
+            "text/event-stream",
+            concat!(
+                "data: {\"choices\": [{\"delta\": {\"role\": \"assistant\"}}]}\n\n",
+                "data: {\"choices\": [{\"delta\": {\"content\": \"Hello\"}}]}\n\n",
+                "data: {\"choices\": [{\"delta\": {\"content\": \" user.\"}}]}\n\n",
+                "data: {\"choices\": [{\"delta\": {}, \"finish_reason\": \"stop\"}]}\n\n",
+                "data: {\"choices\": [], \"usage\": {\"prompt_tokens\": 11, \"completion_tokens\": 3}}\n\n",
+                "data: [DONE]\n\n",
+            ),
+        );
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+
+        let message = fetch_response_to_chat(
+            &ApiConfig::new(
+                base_url,
+                Some("test_key".to_owned()),
+                Some("test_org".to_owned()),
+            )
+            .unwrap(),
+            1,
+            "test_bot",
+            false,
+            &ChatCompletionRequest::builder()
+                .messages(vec![ChatMessage::new(Role::User, "Hello bot.")])
+                .model("gpt-3.5-turbo")
+                .max_tokens(100)
+                .stream(true)
+                .build()
+                .unwrap(),
+            &progress_tx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(message.content, "Hello user.");
+        assert!(!message.truncated);
+
+        let mut deltas = Vec::new();
+        while let Ok(ResponseProgress::Delta(delta)) = progress_rx.try_recv() {
+            deltas.push(delta);
+        }
+        assert_eq!(deltas, vec!["Hello", " user."]);
+        assert_eq!(
+            message.usage,
+            Some(TokenUsage {
+                prompt_tokens: 11,
+                completion_tokens: 3
+            })
+        );
+
+        let request = server.join().unwrap().to_lowercase();
+        assert!(request.starts_with("post /v1/chat/completions http/1.1"));
+        assert!(request.contains("authorization: bearer test_key"));
+        assert!(request.contains("openai-organization: test_org"));
+        assert!(request.contains(r#""stream":true"#));
+        assert!(request.contains(r#""stream_options":{"include_usage":true}"#));
+    }
+
+    #[tokio::test]
+    async fn test_several_responses_to_chat_become_alternates() {
+        let (base_url, server) = serve_once(
+            "application/json",
+            r#"{
+                "choices": [
+                    {"index": 0, "message": {"role": "assistant", "content": "Hello user."}},
+                    {"index": 1, "message": {"role": "assistant", "content": "test_bot: Hi!"}}
+                ],
+                "usage": {"prompt_tokens": 9, "completion_tokens": 5}
+            }"#,
+        );
+        let (progress_tx, _progress_rx) = mpsc::unbounded_channel();
+
+        let message = fetch_response_to_chat(
+            &ApiConfig::new(base_url, None, None).unwrap(),
+            1,
+            "test_bot",
+            false,
+            &ChatCompletionRequest::builder()
+                .messages(vec![ChatMessage::new(Role::User, "Hello bot.")])
+                .model("gpt-3.5-turbo")
+                .max_tokens(100)
+                .n(2)
+                .build()
+                .unwrap(),
+            &progress_tx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(message.content, "Hello user.");
+        let alternates = message.alternates.unwrap();
+        assert_eq!(alternates.chosen, 0);
+        assert_eq!(
+            alternates
+                .responses
+                .iter()
+                .map(|a| a.content.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Hello user.", "Hi!"]
+        );
+
+        let request = server.join().unwrap();
+        assert!(request.contains(r#""n":2"#));
+    }
+}
@@ -0,0 +1,197 @@
+use super::{RawResponse, StreamOptions};
+use crate::{message::TokenUsage, sampling::SamplingParameters};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionRequest {
+    pub messages: Vec<ChatMessage>,
+    pub model: Cow<'static, str>,
+    pub max_tokens: u32,
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+}
+
+impl ChatCompletionRequest {
+    pub fn builder() -> ChatCompletionRequestBuilder {
+        Default::default()
+    }
+}
+
+#[derive(Default)]
+pub struct ChatCompletionRequestBuilder {
+    messages: Vec<ChatMessage>,
+    model: Option<Cow<'static, str>>,
+    max_tokens: Option<u32>,
+    sampling: SamplingParameters,
+    stream: Option<bool>,
+    n: Option<u32>,
+}
+
+impl ChatCompletionRequestBuilder {
+    pub fn messages(mut self, messages: Vec<ChatMessage>) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<Cow<'static, str>>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Set the sampling parameters.
+    ///
+    /// See [`TextCompletionRequestBuilder::sampling`](super::text_completion::TextCompletionRequestBuilder::sampling)
+    /// for more information.
+    pub fn sampling(mut self, sampling: SamplingParameters) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Set the maximum number of tokens to generate.
+    ///
+    /// See [`TextCompletionRequestBuilder::max_tokens`](super::text_completion::TextCompletionRequestBuilder::max_tokens)
+    /// for more information on tokens.
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// When set to true, the response will be sent back as a stream of server-sent events, each
+    /// containing the next few tokens of the response.
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+
+    /// Set how many responses to generate.
+    ///
+    /// See [`TextCompletionRequestBuilder::n`](super::text_completion::TextCompletionRequestBuilder::n)
+    /// for more information.
+    pub fn n(mut self, n: u32) -> Self {
+        self.n = Some(n);
+        self
+    }
+
+    pub fn build(self) -> Result<ChatCompletionRequest, anyhow::Error> {
+        if self.messages.is_empty() {
+            anyhow::bail!("at least one message is required");
+        }
+
+        Ok(ChatCompletionRequest {
+            messages: self.messages,
+            model: self
+                .model
+                .ok_or_else(|| anyhow::anyhow!("model is required"))?,
+            max_tokens: self
+                .max_tokens
+                .ok_or_else(|| anyhow::anyhow!("max_tokens is required"))?,
+            stream: self.stream,
+            // Streamed responses leave out the usage unless it's asked for
+            stream_options: self.stream.filter(|stream| *stream).map(|_| StreamOptions {
+                include_usage: true,
+            }),
+            n: self.n.filter(|n| *n != 1),
+            sampling: self.sampling,
+        })
+    }
+}
+
+/// A single turn in a chat, as understood by the Chat Completions API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Instructions for the assistant. The starting prompt is sent with this role.
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionResponse {
+    choices: Vec<Choice>,
+    usage: Option<TokenUsage>,
+}
+
+impl ChatCompletionResponse {
+    pub fn responses(&self) -> Vec<RawResponse> {
+        self.choices
+            .iter()
+            .map(|choice| {
+                RawResponse::new(&choice.message.content, choice.finish_reason.as_deref())
+            })
+            .collect()
+    }
+
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.usage
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: ChatMessage,
+    // index: u32,
+    finish_reason: Option<String>,
+}
+
+/// One of the server-sent events making up a streamed chat completion.
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionChunk {
+    // The chunk carrying the usage doesn't have any choices
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<TokenUsage>,
+}
+
+impl ChatCompletionChunk {
+    pub fn delta(&self) -> Option<&str> {
+        self.choices
+            .first()
+            .and_then(|choice| choice.delta.content.as_deref())
+    }
+
+    pub fn finish_reason(&self) -> Option<&str> {
+        self.choices
+            .first()
+            .and_then(|choice| choice.finish_reason.as_deref())
+    }
+
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.usage
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    delta: Delta,
+    // Only set on the last chunk with a delta
+    finish_reason: Option<String>,
+}
+
+/// The first delta of a stream only contains the role, and the last one is empty.
+#[derive(Debug, Deserialize)]
+struct Delta {
+    content: Option<String>,
+}
@@ -0,0 +1,147 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::{fmt, time::Duration};
+
+/// Everything that can go wrong when asking OpenAI for a response.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The API responded, but with an error status. When possible, the details are parsed from the
+    /// error object OpenAI includes in the body.
+    Response {
+        status: StatusCode,
+        kind: Option<String>,
+        code: Option<String>,
+        message: String,
+        /// How long the server asked us to wait before trying again.
+        retry_after: Option<Duration>,
+    },
+    /// The request couldn't be sent, or the response couldn't be read.
+    Http(reqwest::Error),
+    /// The response body wasn't shaped like we expected it to be.
+    Decode(serde_json::Error),
+    /// The response was fine, but it didn't contain any choices.
+    NoChoices,
+}
+
+impl ApiError {
+    /// Build an error from an unsuccessful response's status, `Retry-After` header, and body.
+    pub fn from_response(status: StatusCode, retry_after: Option<Duration>, body: &str) -> Self {
+        match serde_json::from_str::<ErrorResponse>(body) {
+            Ok(ErrorResponse { error }) => Self::Response {
+                status,
+                kind: error.kind,
+                code: error.code,
+                message: error.message,
+                retry_after,
+            },
+            // Not every server that speaks OpenAI's protocol speaks its errors too
+            Err(_) => Self::Response {
+                status,
+                kind: None,
+                code: None,
+                message: body.trim().to_owned(),
+                retry_after,
+            },
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Response { status, .. } => Some(*status),
+            Self::Http(e) => e.status(),
+            Self::Decode(_) | Self::NoChoices => None,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Response {
+                status, message, ..
+            } if message.is_empty() => write!(f, "OpenAI responded with {status}"),
+            Self::Response {
+                status, message, ..
+            } => write!(f, "OpenAI responded with {status}: {message}"),
+            Self::Http(e) if e.is_timeout() => write!(f, "request to OpenAI timed out"),
+            Self::Http(e) if e.is_connect() => write!(f, "couldn't connect to OpenAI: {e}"),
+            Self::Http(e) => write!(f, "request to OpenAI failed: {e}"),
+            Self::Decode(e) => write!(f, "couldn't understand OpenAI's response: {e}"),
+            Self::NoChoices => write!(f, "OpenAI's response didn't contain any choices"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            Self::Decode(e) => Some(e),
+            Self::Response { .. } | Self::NoChoices => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Decode(e)
+    }
+}
+
+/// The body OpenAI sends along with an error status.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorObject,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorObject {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    code: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_error_parsed_from_openai_error_body() {
+        let body = r#"{
+            "error": {
+                "message": "Incorrect API key provided: sk-1234.",
+                "type": "invalid_request_error",
+                "param": null,
+                "code": "invalid_api_key"
+            }
+        }"#;
+
+        let e = ApiError::from_response(StatusCode::UNAUTHORIZED, None, body);
+        assert_eq!(
+            e.to_string(),
+            "OpenAI responded with 401 Unauthorized: Incorrect API key provided: sk-1234."
+        );
+        assert!(matches!(
+            e,
+            ApiError::Response { kind: Some(kind), code: Some(code), .. }
+                if kind == "invalid_request_error" && code == "invalid_api_key"
+        ));
+    }
+
+    #[test]
+    fn test_error_from_unexpected_body() {
+        let e = ApiError::from_response(StatusCode::BAD_GATEWAY, None, "upstream unavailable\n");
+        assert_eq!(
+            e.to_string(),
+            "OpenAI responded with 502 Bad Gateway: upstream unavailable"
+        );
+    }
+}
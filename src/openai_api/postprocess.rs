@@ -0,0 +1,106 @@
+//! Completion models don't know where the bot's turn ends. The prompt is a transcript with each
+//! turn written as `Name:\ncontent`, so once the bot has said its piece the model often carries on
+//! writing the user's next line for them, and the bot's answer to that, and so on.
+//!
+//! A stop sequence for the start of the user's turn is sent with every request, which stops most
+//! of that before it's written (and paid for). Not every server supports stop sequences, and only
+//! a few can be sent, so whatever gets through is cut off here.
+
+use crate::sampling::MAX_STOP_SEQUENCES;
+
+/// The user's stop sequences, plus one that ends the response where the bot would start writing
+/// the user's next turn. If the user has used up every stop sequence there is, theirs are kept.
+pub fn stop_sequences(stop: &[String], your_name: &str) -> Vec<String> {
+    let mut stop = stop.to_vec();
+    let users_turn = format!("\n{your_name}:");
+    if stop.len() < MAX_STOP_SEQUENCES && !stop.contains(&users_turn) {
+        stop.push(users_turn);
+    }
+
+    stop
+}
+
+/// Everything before the first line where the bot started writing the user's turn.
+pub fn cut_hallucinated_turns<'a>(completion: &'a str, your_name: &str) -> &'a str {
+    let users_turn = format!("{your_name}:");
+    let mut offset = 0;
+    for line in completion.split_inclusive('\n') {
+        // A response that's nothing but the user's turn is stranger still, so it's left alone
+        if offset > 0 && line.trim_start().starts_with(&users_turn) {
+            return completion[..offset].trim_end();
+        }
+        offset += line.len();
+    }
+
+    completion
+}
+
+/// Models sometimes start the bot's response with its name, which is already shown in the UI.
+pub fn strip_leading_name<'a>(response: &'a str, their_name: &str) -> &'a str {
+    let response = response.trim_start();
+    response
+        .strip_prefix(their_name)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .unwrap_or(response)
+        .trim_start()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openai_api::text_completion::TextCompletionResponse;
+    use pretty_assertions::assert_eq;
+
+    // What the bot's message ends up being for each of the choices in a recorded response
+    fn clean_up(recorded: &str) -> Vec<String> {
+        let response: TextCompletionResponse = serde_json::from_str(recorded).unwrap();
+        response
+            .responses()
+            .iter()
+            .map(|response| {
+                let text = cut_hallucinated_turns(&response.text, "User");
+                strip_leading_name(text, "Bot").trim_end().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hallucinated_turns_are_cut_from_recorded_completions() {
+        assert_eq!(
+            clean_up(include_str!("fixtures/completion_with_hallucinated_turns.json")),
+            vec!["The capital of France is Paris. It's been the capital since the 10th century, give or take a few interruptions.".to_owned()]
+        );
+        assert_eq!(
+            clean_up(include_str!("fixtures/completion_with_inline_turn.json")),
+            vec![
+                "Mount Everest, at 8,849 metres.".to_owned(),
+                "That'd be Mount Everest, which is 8,849 metres tall.".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_completions_without_extra_turns_are_left_alone() {
+        // The poem has a line starting with "User", but it isn't the user's turn
+        assert_eq!(
+            clean_up(include_str!("fixtures/completion_without_extra_turns.json")),
+            vec!["Here's one:\n\nUser interfaces glow,\nkeystrokes fall like spring rain,\nthe bot answers back.".to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_stop_sequence_for_users_turn_is_added_when_theres_room() {
+        assert_eq!(stop_sequences(&[], "Zelda"), vec!["\nZelda:".to_owned()]);
+
+        let already_there = vec!["###".to_owned(), "\nZelda:".to_owned()];
+        assert_eq!(stop_sequences(&already_there, "Zelda"), already_there);
+
+        let full = vec![
+            "a".to_owned(),
+            "b".to_owned(),
+            "c".to_owned(),
+            "d".to_owned(),
+        ];
+        assert_eq!(stop_sequences(&full, "Zelda"), full);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::message::TokenUsage;
+
+/// What OpenAI charges, in dollars per 1,000 tokens, for the prompt and the completion respectively.
+///
+/// Models are matched by prefix so that dated snapshots (like "gpt-4-0613") are priced like the
+/// model they're a snapshot of. Longer prefixes have to come first. Prices change every so often,
+/// so check [OpenAI's pricing page](https://openai.com/pricing) if these look off.
+const PRICES_PER_1K_TOKENS: &[(&str, f64, f64)] = &[
+    ("gpt-4-32k", 0.06, 0.12),
+    ("gpt-4", 0.03, 0.06),
+    ("gpt-3.5-turbo-16k", 0.003, 0.004),
+    ("gpt-3.5-turbo", 0.0015, 0.002),
+    ("text-davinci", 0.02, 0.02),
+    ("text-curie", 0.002, 0.002),
+    ("text-babbage", 0.0005, 0.0005),
+    ("text-ada", 0.0004, 0.0004),
+];
+
+/// Estimate what `usage` of `model` cost, in dollars. Returns `None` for models we don't know the
+/// price of, like ones served by something other than OpenAI.
+pub fn estimate_cost(model: &str, usage: TokenUsage) -> Option<f64> {
+    PRICES_PER_1K_TOKENS
+        .iter()
+        .find(|(prefix, ..)| model.starts_with(prefix))
+        .map(|(_, prompt_price, completion_price)| {
+            (usage.prompt_tokens as f64 * prompt_price
+                + usage.completion_tokens as f64 * completion_price)
+                / 1000.0
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_cost_uses_most_specific_price() {
+        let usage = TokenUsage {
+            prompt_tokens: 1000,
+            completion_tokens: 500,
+        };
+
+        assert_eq!(estimate_cost("gpt-4-0613", usage), Some(0.06));
+        assert_eq!(estimate_cost("gpt-4-32k-0613", usage), Some(0.12));
+        assert_eq!(estimate_cost("text-davinci-003", usage), Some(0.03));
+        assert_eq!(estimate_cost("llama-2-7b-chat", usage), None);
+    }
+}
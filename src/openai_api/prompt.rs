@@ -0,0 +1,66 @@
+use super::chat_completion::{ChatMessage, Role};
+use crate::message::Message;
+
+/// Format the conversation as a transcript for the Completions API, which only understands a flat
+/// string prompt. When `continuing`, the last message is the bot's response that was cut off, and
+/// it's left open for the bot to carry on writing.
+pub fn create_prompt_from_messages(
+    starting_prompt: &str,
+    messages: &[Message],
+    continuing: bool,
+) -> String {
+    let messages_len = messages.iter().map(|m| m.content.len()).sum::<usize>();
+    // Really, the final prompt will be longer than this due to also including names and timestamps,
+    // but this is a good starting point.
+    let mut prompt = String::with_capacity(messages_len + starting_prompt.len());
+
+    if !starting_prompt.is_empty() {
+        prompt.push_str(starting_prompt);
+        prompt.push_str("\n\n")
+    }
+
+    for message in messages {
+        prompt.push_str(format!("{}:\n{}\n\n", &message.sender, &message.content).as_str());
+    }
+    if continuing {
+        prompt.truncate(prompt.trim_end().len());
+    }
+
+    prompt
+}
+
+// Chat models treat every message they're sent as finished, so they have to be asked to carry on
+const CONTINUE_INSTRUCTION: &str = "Your last message was cut off. Continue it from exactly where \
+    it stopped, even if that's partway through a word or sentence. Don't repeat any of it.";
+
+/// Convert the conversation into role-tagged messages for the Chat Completions API. When
+/// `continuing`, the last message is the bot's response that was cut off, and the bot is told to
+/// carry on with it.
+pub fn create_chat_messages_from_messages(
+    starting_prompt: &str,
+    your_name: &str,
+    messages: &[Message],
+    continuing: bool,
+) -> Vec<ChatMessage> {
+    let mut chat_messages = Vec::with_capacity(messages.len() + 1);
+
+    if !starting_prompt.is_empty() {
+        chat_messages.push(ChatMessage::new(Role::System, starting_prompt));
+    }
+
+    // The chat API doesn't care about names, only about who said what.
+    chat_messages.extend(messages.iter().map(|message| {
+        let role = if message.sender == your_name {
+            Role::User
+        } else {
+            Role::Assistant
+        };
+
+        ChatMessage::new(role, message.content.as_str())
+    }));
+    if continuing {
+        chat_messages.push(ChatMessage::new(Role::System, CONTINUE_INSTRUCTION));
+    }
+
+    chat_messages
+}
@@ -0,0 +1,136 @@
+use super::ApiError;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Waiting longer than this between attempts isn't worth it; the user would rather just try again.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Decides whether a failed request should be tried again, and how long to wait before doing so.
+///
+/// Rate limits and server errors are usually over quickly, so they're retried with exponential
+/// backoff. When OpenAI says how long to wait with a `Retry-After` header, that's respected
+/// instead. Anything else (like a bad API key) won't be fixed by trying again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        Self {
+            // The first attempt always happens
+            max_attempts: max_attempts.max(1),
+            base_delay,
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// How long to wait before the next attempt, given that attempt number `attempt` (starting
+    /// from 1) failed with `error`. Returns `None` if the request shouldn't be retried.
+    pub fn delay_before_retry(&self, attempt: u32, error: &ApiError) -> Option<Duration> {
+        if attempt >= self.max_attempts || !is_retryable(error) {
+            return None;
+        }
+
+        if let ApiError::Response {
+            retry_after: Some(retry_after),
+            ..
+        } = error
+        {
+            return Some(*retry_after).filter(|retry_after| *retry_after <= MAX_DELAY);
+        }
+
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1));
+
+        Some(backoff.min(MAX_DELAY))
+    }
+}
+
+fn is_retryable(error: &ApiError) -> bool {
+    match error {
+        // Running out of credits is also reported as a 429, but waiting won't help with that
+        ApiError::Response {
+            code: Some(code), ..
+        } if code == "insufficient_quota" => false,
+        ApiError::Response { status, .. } => {
+            *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+        }
+        ApiError::Http(e) => {
+            e.is_timeout() || e.is_connect() || e.status().is_some_and(|s| s.is_server_error())
+        }
+        ApiError::Decode(_) | ApiError::NoChoices => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn error(status: StatusCode, code: Option<&str>, retry_after: Option<Duration>) -> ApiError {
+        ApiError::Response {
+            status,
+            kind: None,
+            code: code.map(ToOwned::to_owned),
+            message: String::new(),
+            retry_after,
+        }
+    }
+
+    #[test]
+    fn test_server_errors_back_off_exponentially() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(500));
+        let e = error(StatusCode::INTERNAL_SERVER_ERROR, None, None);
+
+        let delays: Vec<_> = (1..=4)
+            .map(|attempt| policy.delay_before_retry(attempt, &e))
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_millis(500)),
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rate_limits_honor_retry_after() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(500));
+        let e = error(
+            StatusCode::TOO_MANY_REQUESTS,
+            Some("rate_limit_exceeded"),
+            Some(Duration::from_secs(7)),
+        );
+
+        assert_eq!(
+            policy.delay_before_retry(1, &e),
+            Some(Duration::from_secs(7))
+        );
+    }
+
+    #[test]
+    fn test_permanent_errors_are_not_retried() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(500));
+
+        for e in [
+            error(StatusCode::UNAUTHORIZED, Some("invalid_api_key"), None),
+            error(
+                StatusCode::TOO_MANY_REQUESTS,
+                Some("insufficient_quota"),
+                None,
+            ),
+            ApiError::NoChoices,
+        ] {
+            assert_eq!(policy.delay_before_retry(1, &e), None, "{e}");
+        }
+    }
+}
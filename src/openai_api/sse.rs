@@ -0,0 +1,61 @@
+/// The marker OpenAI sends as the final event of a streamed response.
+pub const DONE: &str = "[DONE]";
+
+/// An incremental parser for `text/event-stream` bodies.
+///
+/// Response bodies arrive in arbitrarily sized chunks that don't line up with event boundaries (or
+/// even UTF-8 character boundaries), so the parser holds on to any partial line until the rest of
+/// it shows up. Only `data` fields are kept since OpenAI doesn't send anything else.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feed a chunk of the response body to the parser, returning the data of every event that
+    /// was completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(newline) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                // A blank line dispatches the event
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_owned());
+            }
+            // Comments (lines starting with ':') and other fields are ignored
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_events_split_across_chunks() {
+        let mut parser = SseParser::default();
+
+        assert_eq!(parser.push(b"data: {\"a\""), Vec::<String>::new());
+        assert_eq!(parser.push(b": 1}\n"), Vec::<String>::new());
+        assert_eq!(
+            parser.push(b"\ndata: {\"b\": 2}\r\n\r\n: keep-alive\n\n"),
+            vec!["{\"a\": 1}".to_owned(), "{\"b\": 2}".to_owned(),]
+        );
+        assert_eq!(parser.push(b"data: [DONE]\n\n"), vec![DONE.to_owned()]);
+    }
+}
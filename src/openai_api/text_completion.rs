@@ -1,3 +1,5 @@
+use super::{RawResponse, StreamOptions};
+use crate::{message::TokenUsage, sampling::SamplingParameters};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 
@@ -5,8 +7,15 @@ use std::borrow::Cow;
 pub struct TextCompletionRequest {
     pub prompt: Cow<'static, str>,
     pub model: Cow<'static, str>,
-    pub temperature: f32,
     pub max_tokens: u32,
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
 }
 
 impl TextCompletionRequest {
@@ -19,8 +28,10 @@ impl TextCompletionRequest {
 pub struct TextCompletionRequestBuilder {
     prompt: Option<Cow<'static, str>>,
     model: Option<Cow<'static, str>>,
-    temperature: Option<f32>,
     max_tokens: Option<u32>,
+    sampling: SamplingParameters,
+    stream: Option<bool>,
+    n: Option<u32>,
 }
 
 impl TextCompletionRequestBuilder {
@@ -34,8 +45,10 @@ impl TextCompletionRequestBuilder {
         self
     }
 
-    pub fn _temperature(mut self, temperature: f32) -> Self {
-        self.temperature = Some(temperature);
+    /// Set the sampling parameters. Any that aren't set are left up to the server, which for
+    /// OpenAI means a temperature of 1.
+    pub fn sampling(mut self, sampling: SamplingParameters) -> Self {
+        self.sampling = sampling;
         self
     }
 
@@ -50,17 +63,37 @@ impl TextCompletionRequestBuilder {
         self
     }
 
+    /// When set to true, the response will be sent back as a stream of server-sent events, each
+    /// containing the next few tokens of the response.
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+
+    /// How many responses to generate. Each one is a separate choice in the response, and each
+    /// one's tokens count towards the usage.
+    pub fn n(mut self, n: u32) -> Self {
+        self.n = Some(n);
+        self
+    }
+
     pub fn build(self) -> Result<TextCompletionRequest, anyhow::Error> {
         Ok(TextCompletionRequest {
             prompt: self.prompt.expect("prompt is required"),
             model: self
                 .model
-                .map(Into::into)
                 .ok_or_else(|| anyhow::anyhow!("model is required"))?,
-            temperature: self.temperature.unwrap_or_default(),
             max_tokens: self
                 .max_tokens
                 .ok_or_else(|| anyhow::anyhow!("max_tokens is required"))?,
+            stream: self.stream,
+            // Ask for the usage to be sent at the end of the stream, since it isn't otherwise
+            stream_options: self.stream.filter(|stream| *stream).map(|_| StreamOptions {
+                include_usage: true,
+            }),
+            // Some local servers don't know about `n`, so it's only sent when it isn't the default
+            n: self.n.filter(|n| *n != 1),
+            sampling: self.sampling,
         })
     }
 }
@@ -73,17 +106,19 @@ pub struct TextCompletionResponse {
     // created: DateTime<Utc>,
     // model: String,
     choices: Vec<Choice>,
-    // usage: Usage,
+    usage: Option<TokenUsage>,
 }
 
 impl TextCompletionResponse {
-    pub fn message(&self) -> String {
+    pub fn responses(&self) -> Vec<RawResponse> {
         self.choices
-            .first()
-            .expect("choices is not empty")
-            .text
-            .trim()
-            .to_owned()
+            .iter()
+            .map(|choice| RawResponse::new(&choice.text, choice.finish_reason.as_deref()))
+            .collect()
+    }
+
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.usage
     }
 }
 
@@ -92,12 +127,31 @@ struct Choice {
     text: String,
     // index: u32,
     // logprobs: Option<u32>,
-    // finish_reason: String,
+    // Only set on the last chunk of a streamed response
+    finish_reason: Option<String>,
 }
 
-// #[derive(Debug, Deserialize)]
-// struct Usage {
-//     prompt_tokens: u32,
-//     completion_tokens: u32,
-//     total_tokens: u32,
-// }
+/// One of the server-sent events making up a streamed text completion.
+#[derive(Debug, Deserialize)]
+pub struct TextCompletionChunk {
+    // The chunk carrying the usage doesn't have any choices
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<TokenUsage>,
+}
+
+impl TextCompletionChunk {
+    pub fn delta(&self) -> Option<&str> {
+        self.choices.first().map(|choice| choice.text.as_str())
+    }
+
+    pub fn finish_reason(&self) -> Option<&str> {
+        self.choices
+            .first()
+            .and_then(|choice| choice.finish_reason.as_deref())
+    }
+
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.usage
+    }
+}
@@ -0,0 +1,66 @@
+mod mock;
+
+use crate::{message::Message, sampling::SamplingParameters};
+use clap::ValueEnum;
+use futures::future::BoxFuture;
+use tokio::sync::mpsc::UnboundedSender;
+
+pub use mock::{EchoProvider, ScriptedProvider};
+
+/// Something that can come up with the bot's side of the conversation.
+///
+/// The backend doesn't care where responses come from, so long as they come eventually. Providers
+/// are expected to be cheap to call; any setup should happen when they're created.
+pub trait Provider: Send + Sync {
+    fn fetch_response(
+        &self,
+        request: ResponseRequest,
+        progress_tx: UnboundedSender<ResponseProgress>,
+    ) -> BoxFuture<'static, Result<Message, anyhow::Error>>;
+}
+
+/// Everything a [`Provider`] needs to know to respond to the conversation.
+#[derive(Debug, Clone)]
+pub struct ResponseRequest {
+    /// An ID for the bot's message. It's replaced with the message's database ID once it's saved.
+    pub id: u64,
+    pub starting_prompt: String,
+    pub your_name: String,
+    pub their_name: String,
+    /// The most recent messages of the conversation, oldest first
+    pub history: Vec<Message>,
+    pub model: String,
+    pub max_tokens: u32,
+    /// When true, the provider should send the response through `progress_tx` as it's written
+    pub stream: bool,
+    /// How many responses to come up with. When there's more than one, they're returned as the
+    /// message's alternates. Providers that can only come up with one at a time can ignore this.
+    pub n: u32,
+    /// Providers that don't sample from a model can ignore these.
+    pub sampling: SamplingParameters,
+    /// When true, the last message of the history is the bot's, cut off partway through, and the
+    /// response should carry on from where it stopped. Only the new part is returned.
+    pub continuing: bool,
+}
+
+/// Updates sent by an in-flight request before its final [`Message`] is ready.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseProgress {
+    /// The next few tokens of a streamed response.
+    Delta(String),
+    /// The last attempt failed, and attempt number `attempt` will be made shortly. Anything
+    /// streamed so far should be thrown away.
+    Retrying { attempt: u32, max_attempts: u32 },
+}
+
+/// The providers that can be selected with `--provider` or the `PROVIDER` environment variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProviderKind {
+    /// Fetch responses from the OpenAI API.
+    #[value(name = "openai")]
+    OpenAi,
+    /// Respond by repeating the user's last message back to them.
+    Echo,
+    /// Respond with lines read from a script file, in order.
+    Scripted,
+}
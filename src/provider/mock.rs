@@ -0,0 +1,172 @@
+//! Providers that never leave the machine, for running the app without network access or an API
+//! key. Both are deterministic, which makes them handy for demos and tests.
+
+use super::{Provider, ResponseProgress, ResponseRequest};
+use crate::message::Message;
+use anyhow::Context;
+use futures::future::{self, BoxFuture, FutureExt};
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Responds to every message by repeating it back.
+#[derive(Debug, Default)]
+pub struct EchoProvider;
+
+impl Provider for EchoProvider {
+    fn fetch_response(
+        &self,
+        request: ResponseRequest,
+        progress_tx: UnboundedSender<ResponseProgress>,
+    ) -> BoxFuture<'static, Result<Message, anyhow::Error>> {
+        let content = request
+            .history
+            .iter()
+            .rev()
+            .find(|message| message.sender == request.your_name)
+            .map(|message| message.content.clone())
+            .unwrap_or_default();
+
+        future::ready(Ok(respond(request, content, &progress_tx))).boxed()
+    }
+}
+
+/// Responds with each line of a script in turn, starting over once every line has been used.
+#[derive(Debug)]
+pub struct ScriptedProvider {
+    lines: Vec<String>,
+    next_line: AtomicUsize,
+}
+
+impl ScriptedProvider {
+    pub fn new(lines: Vec<String>) -> Result<Self, anyhow::Error> {
+        if lines.is_empty() {
+            anyhow::bail!("a script must contain at least one line");
+        }
+
+        Ok(Self {
+            lines,
+            next_line: AtomicUsize::new(0),
+        })
+    }
+
+    /// Load a script from a file. Blank lines are skipped.
+    pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let script = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read script from {}", path.display()))?;
+        let lines = script
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+
+        Self::new(lines)
+    }
+}
+
+impl Provider for ScriptedProvider {
+    fn fetch_response(
+        &self,
+        request: ResponseRequest,
+        progress_tx: UnboundedSender<ResponseProgress>,
+    ) -> BoxFuture<'static, Result<Message, anyhow::Error>> {
+        let index = self.next_line.fetch_add(1, Ordering::Relaxed) % self.lines.len();
+        let content = self.lines[index].clone();
+
+        future::ready(Ok(respond(request, content, &progress_tx))).boxed()
+    }
+}
+
+fn respond(
+    request: ResponseRequest,
+    content: String,
+    progress_tx: &UnboundedSender<ResponseProgress>,
+) -> Message {
+    if request.stream {
+        // Stream the response a word at a time, like the real thing
+        for word in content.split_inclusive(' ') {
+            let _ = progress_tx.send(ResponseProgress::Delta(word.to_owned()));
+        }
+    }
+
+    Message {
+        id: request.id,
+        sender: request.their_name,
+        content,
+        timestamp: chrono::Utc::now(),
+        usage: None,
+        alternates: None,
+        parent: None,
+        siblings: None,
+        sampling: None,
+        truncated: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::test_message;
+    use pretty_assertions::assert_eq;
+    use tokio::sync::mpsc;
+
+    fn request(history: Vec<Message>) -> ResponseRequest {
+        ResponseRequest {
+            id: history.len() as u64,
+            starting_prompt: String::new(),
+            your_name: "test_user".to_owned(),
+            their_name: "test_bot".to_owned(),
+            history,
+            model: "mock".to_owned(),
+            max_tokens: 100,
+            stream: true,
+            n: 1,
+            sampling: Default::default(),
+            continuing: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scripted_provider_cycles_through_script() {
+        let provider =
+            ScriptedProvider::new(vec!["Hello user.".to_owned(), "Goodbye.".to_owned()]).unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let mut responses = Vec::new();
+        for _ in 0..3 {
+            let message = provider
+                .fetch_response(request(Vec::new()), tx.clone())
+                .await
+                .unwrap();
+            assert_eq!(message.sender, "test_bot");
+            responses.push(message.content);
+        }
+        assert_eq!(responses, vec!["Hello user.", "Goodbye.", "Hello user."]);
+
+        let mut streamed = String::new();
+        while let Ok(ResponseProgress::Delta(delta)) = rx.try_recv() {
+            streamed.push_str(&delta);
+        }
+        assert_eq!(streamed, "Hello user.Goodbye.Hello user.");
+    }
+
+    #[tokio::test]
+    async fn test_echo_provider_repeats_last_user_message() {
+        let history = vec![
+            test_message(1, "test_user", "Is anybody there?"),
+            test_message(2, "test_bot", "Is anybody there?"),
+            test_message(3, "test_user", "Hello?"),
+        ];
+        let (tx, _rx) = mpsc::unbounded_channel();
+
+        let message = EchoProvider
+            .fetch_response(request(history), tx)
+            .await
+            .unwrap();
+        assert_eq!(message.id, 3);
+        assert_eq!(message.content, "Hello?");
+    }
+}
@@ -0,0 +1,112 @@
+//! The knobs that change how the bot picks its words. Each one is optional, and anything that
+//! isn't set is left up to the server.
+
+use serde::{Deserialize, Serialize};
+
+/// OpenAI won't take any more stop sequences than this.
+pub const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Sampling parameters sent along with every request for the bot's response. They're recorded with
+/// each of the bot's messages, so it's possible to tell later what produced it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingParameters {
+    /// How random the response is, from 0 to 2. Higher is more creative and less focused.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Only sample from the most likely tokens making up this much probability, from 0 to 1. It's
+    /// usually best to change this or the temperature, but not both.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// From -2 to 2. Positive values make the bot more likely to talk about something new.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// From -2 to 2. Positive values make the bot less likely to repeat itself word for word.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// The response ends before any of these would be written.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Ask for the same response every time the same request is made with the same seed. This is
+    /// only a best effort.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+impl SamplingParameters {
+    /// Check that every parameter is in the range OpenAI accepts, so that mistakes are caught
+    /// before they're sent.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let ranges = [
+            ("temperature", self.temperature, 0.0..=2.0),
+            ("top_p", self.top_p, 0.0..=1.0),
+            ("presence_penalty", self.presence_penalty, -2.0..=2.0),
+            ("frequency_penalty", self.frequency_penalty, -2.0..=2.0),
+        ];
+        for (name, value, range) in ranges {
+            if let Some(value) = value.filter(|value| !range.contains(value)) {
+                anyhow::bail!(
+                    "{name} must be between {} and {}, not {value}",
+                    range.start(),
+                    range.end()
+                );
+            }
+        }
+        if self.stop.len() > MAX_STOP_SEQUENCES {
+            anyhow::bail!("there can't be more than {MAX_STOP_SEQUENCES} stop sequences");
+        }
+
+        Ok(())
+    }
+}
+
+/// Stop sequences are written as a comma-separated list, since they're set through environment
+/// variables and one-line text boxes. They often start with a newline, which is written as `\n`.
+pub fn parse_stop_sequences(list: &str) -> Vec<String> {
+    list.split(',')
+        .filter(|sequence| !sequence.trim().is_empty())
+        .map(|sequence| sequence.replace("\\n", "\n"))
+        .collect()
+}
+
+/// Write stop sequences the way [`parse_stop_sequences`] reads them.
+pub fn format_stop_sequences(stop: &[String]) -> String {
+    stop.iter()
+        .map(|sequence| sequence.replace('\n', "\\n"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_stop_sequences_survive_being_written_out() {
+        let stop = parse_stop_sequences("\\nUser:, ###,,");
+        assert_eq!(stop, vec!["\nUser:".to_owned(), " ###".to_owned()]);
+        assert_eq!(parse_stop_sequences(&format_stop_sequences(&stop)), stop);
+        assert!(parse_stop_sequences("").is_empty());
+    }
+
+    #[test]
+    fn test_out_of_range_parameters_are_rejected() {
+        let mut sampling = SamplingParameters {
+            temperature: Some(0.7),
+            presence_penalty: Some(-2.0),
+            ..Default::default()
+        };
+        assert!(sampling.validate().is_ok());
+
+        sampling.top_p = Some(1.5);
+        assert_eq!(
+            sampling.validate().unwrap_err().to_string(),
+            "top_p must be between 0 and 1, not 1.5"
+        );
+
+        sampling.top_p = None;
+        sampling.stop = vec!["a".to_owned(); 5];
+        assert!(sampling.validate().is_err());
+    }
+}
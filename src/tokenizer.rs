@@ -0,0 +1,113 @@
+//! Counting tokens locally, so that we know how much of the conversation fits in a request before
+//! sending it.
+
+use crate::message::Message;
+use anyhow::Context;
+use tiktoken_rs::CoreBPE;
+
+/// Every message costs a few tokens on top of its content. The chat endpoint wraps each message
+/// with role markers, and transcripts separate messages with the sender's name and some newlines.
+const TOKENS_PER_MESSAGE: usize = 4;
+
+pub struct Tokenizer {
+    bpe: CoreBPE,
+    // The most tokens the model can handle at once, counting both the prompt and the response
+    context_window: usize,
+}
+
+impl Tokenizer {
+    /// Load the encoding used by `model`. Models that OpenAI doesn't know about (like ones served
+    /// locally) are assumed to use the same encoding as `gpt-3.5-turbo`, which is close enough
+    /// for estimating how much will fit.
+    pub fn for_model(model: &str) -> Result<Self, anyhow::Error> {
+        let bpe = match tiktoken_rs::get_bpe_from_model(model) {
+            Ok(bpe) => bpe,
+            Err(_) => tiktoken_rs::cl100k_base().context("loading default tokenizer")?,
+        };
+
+        Ok(Self {
+            bpe,
+            context_window: tiktoken_rs::model::get_context_size(model),
+        })
+    }
+
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+
+    fn count_message_tokens(&self, message: &Message) -> usize {
+        self.count_tokens(&message.sender)
+            + self.count_tokens(&message.content)
+            + TOKENS_PER_MESSAGE
+    }
+
+    /// The most recent messages that fit in a prompt alongside the starting prompt, while leaving
+    /// room in the context window for a response of up to `max_tokens` tokens.
+    ///
+    /// The last message is always included, even if it doesn't fit. The bot has to see what it's
+    /// responding to, and OpenAI will explain the problem better than we could.
+    pub fn fit_history<'a>(
+        &self,
+        starting_prompt: &str,
+        messages: &'a [Message],
+        max_tokens: u32,
+    ) -> &'a [Message] {
+        let mut budget = self
+            .context_window
+            .saturating_sub(max_tokens as usize)
+            .saturating_sub(self.count_tokens(starting_prompt) + TOKENS_PER_MESSAGE);
+
+        let mut start = messages.len();
+        for message in messages.iter().rev() {
+            let tokens = self.count_message_tokens(message);
+            if tokens > budget && start < messages.len() {
+                break;
+            }
+
+            budget = budget.saturating_sub(tokens);
+            start -= 1;
+        }
+
+        &messages[start..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::test_message;
+    use pretty_assertions::assert_eq;
+
+    fn message(id: u64, content: String) -> Message {
+        test_message(id, "test_user", &content)
+    }
+
+    #[test]
+    fn test_history_fits_in_context_window() {
+        let tokenizer = Tokenizer::for_model("text-davinci-003").unwrap();
+        assert_eq!(tokenizer.context_window, 4097);
+
+        // Each message is a little over 1,000 tokens, so only three of them fit alongside a
+        // 100 token response
+        let messages: Vec<_> = (0..5)
+            .map(|id| message(id, "hello ".repeat(1000)))
+            .collect();
+        let history = tokenizer.fit_history("test prompt", &messages, 100);
+        assert_eq!(history, &messages[2..]);
+
+        // Asking for a longer response leaves less room for history
+        let history = tokenizer.fit_history("test prompt", &messages, 2000);
+        assert_eq!(history, &messages[3..]);
+    }
+
+    #[test]
+    fn test_last_message_is_always_included() {
+        let tokenizer = Tokenizer::for_model("text-davinci-003").unwrap();
+        let messages = vec![message(0, "hello ".repeat(10_000))];
+
+        assert_eq!(
+            tokenizer.fit_history("test prompt", &messages, 100),
+            &messages[..]
+        );
+    }
+}